@@ -1,8 +1,11 @@
-use std::fs;
+use std::{fs, path::PathBuf, process};
 
-use diff_man::DiffManager;
+use diff_man::{
+    DiffManager,
+    parser::{ParseOptions, Parser as DiffParser},
+};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
@@ -15,6 +18,42 @@ pub struct Args {
 
     #[arg(short = 't')]
     pub target_root: std::path::PathBuf,
+
+    /// count the lines of the hunks instead of trusting their ranges
+    #[arg(long)]
+    pub recount: bool,
+
+    /// skip the text around the diffs, such as commit headers
+    #[arg(long)]
+    pub lenient: bool,
+
+    /// format of the diff
+    #[arg(short = 'f', long, value_enum, default_value_t = Format::Git)]
+    pub format: Format,
+
+    /// parent a combined diff is projected on, 1 for the first
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub parent: u64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// `git diff`
+    Git,
+    /// `diff -u`, without git headers
+    Unified,
+    /// `diff -c`
+    Context,
+    /// default format of `diff`
+    Normal,
+    /// `diff -e`, read against the target
+    Ed,
+    /// `git diff --cc` of a merge, against the parent given by `--parent`
+    Combined,
 }
 
 #[derive(Debug, Subcommand)]
@@ -23,18 +62,84 @@ pub enum Mode {
     Apply,
     #[command()]
     Revert,
+    /// check whether the diff applies, without writing anything
+    #[command()]
+    Check {
+        /// check reverting the diff instead
+        #[arg(short = 'R', long)]
+        reverse: bool,
+    },
 }
 
 fn main() {
     let args = Args::parse();
-    let diff_src =
-        fs::read_to_string(args.diff_path).expect("cannot read diff src");
-    let diffs =
-        DiffManager::parse(&diff_man::diff::DiffFormat::GitUdiff, &diff_src)
-            .expect("cannot parse given diff");
+    let diff_src = fs::read(args.diff_path).expect("cannot read diff src");
+    let options = ParseOptions {
+        recount: args.recount,
+        lenient: args.lenient,
+    };
+    let mut diffs = match args.format {
+        Format::Git => DiffParser::parse_git_udiff_with(&diff_src, &options),
+        Format::Unified => DiffParser::parse_unified_with(&diff_src, &options),
+        Format::Context => DiffParser::parse_context_with(&diff_src, &options),
+        Format::Normal => DiffParser::parse_normal_with(&diff_src, &options),
+        Format::Ed => DiffParser::parse_ed_bytes(&diff_src, &args.target_root),
+        Format::Combined => {
+            DiffParser::parse_combined_bytes(&diff_src).map(|combined| {
+                let parent =
+                    usize::try_from(args.parent - 1).unwrap_or(usize::MAX);
+                combined.project(parent).unwrap_or_else(|_| {
+                    eprintln!("the merge has no parent {}", args.parent);
+                    process::exit(1);
+                })
+            })
+        }
+    }
+    .expect("cannot parse given diff");
+    // a diff of a single file does not name it, the target is this file
+    let mut target_root = args.target_root;
+    if target_root.is_file() {
+        let name = PathBuf::from(target_root.file_name().unwrap_or_default());
+        for diff in &mut diffs.diff {
+            if diff.path.as_os_str().is_empty() {
+                diff.path = name.clone();
+                diff.old_path = name.clone();
+            }
+        }
+        target_root.pop();
+    }
     match args.mode {
-        Mode::Apply => diffs.apply(&args.target_root),
-        Mode::Revert => diffs.revert(&args.target_root),
+        Mode::Apply => diffs.apply(&target_root),
+        Mode::Revert => diffs.revert(&target_root),
+        Mode::Check { reverse } => {
+            let report = if reverse {
+                diffs.check_revert(&target_root)
+            } else {
+                DiffManager::check(&diffs, &target_root)
+            };
+            for file in &report.files {
+                let path = file.path.display();
+                for hunk in &file.hunks {
+                    println!(
+                        "{path}: hunk #{} ok (offset {}, fuzz {})",
+                        hunk.hunk + 1,
+                        hunk.offset,
+                        hunk.fuzz
+                    );
+                }
+                for rejection in &file.rejected {
+                    println!(
+                        "{path}: hunk #{} FAILED: {}",
+                        rejection.hunk + 1,
+                        rejection.error.reason()
+                    );
+                }
+            }
+            if !report.is_ok() {
+                process::exit(1);
+            }
+            Ok(())
+        }
     }
     .expect("failed to execute");
 }
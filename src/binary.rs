@@ -0,0 +1,185 @@
+use miniz_oxide::{
+    deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib,
+};
+
+const BASE85: &[u8; 85] = b"0123456789\
+ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+abcdefghijklmnopqrstuvwxyz\
+!#$%&()*+-;<=>?@^_`{|}~";
+
+/// max number of bytes encoded on a line of a binary patch
+pub(crate) const LINE_BYTES: usize = 52;
+
+fn base85_value(c: u8) -> Option<u32> {
+    BASE85.iter().position(|b| *b == c).map(|v| v as u32)
+}
+
+/// decode `len` bytes from base85 groups of 5 characters
+pub(crate) fn decode_base85(src: &str, len: usize) -> Option<Vec<u8>> {
+    let src = src.as_bytes();
+    if src.len() != len.div_ceil(4) * 5 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(len + 3);
+    for group in src.chunks(5) {
+        let mut acc: u32 = 0;
+        for c in group {
+            acc = acc.checked_mul(85)?.checked_add(base85_value(*c)?)?;
+        }
+        out.extend_from_slice(&acc.to_be_bytes());
+    }
+    out.truncate(len);
+    Some(out)
+}
+
+/// encode `data` in base85, padding the last group with zeros
+pub(crate) fn encode_base85(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut acc = u32::from_be_bytes(group);
+        let mut encoded = [0u8; 5];
+        for c in encoded.iter_mut().rev() {
+            *c = BASE85[(acc % 85) as usize];
+            acc /= 85;
+        }
+        out.extend(encoded.iter().map(|c| *c as char));
+    }
+    out
+}
+
+/// number of bytes on a line, given by its first character
+pub(crate) fn line_len(c: char) -> Option<usize> {
+    match c {
+        'A'..='Z' => Some(c as usize - 'A' as usize + 1),
+        'a'..='z' => Some(c as usize - 'a' as usize + 27),
+        _ => None,
+    }
+}
+
+/// first character of a line holding `len` bytes
+pub(crate) fn line_len_char(len: usize) -> char {
+    match len {
+        1..=26 => (b'A' + len as u8 - 1) as char,
+        _ => (b'a' + len as u8 - 27) as char,
+    }
+}
+
+pub(crate) fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    decompress_to_vec_zlib(data).ok()
+}
+
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    compress_to_vec_zlib(data, 6)
+}
+
+/// read a size of the delta header, 7 bits per byte, least significant first
+fn delta_size(delta: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos)?;
+        *pos += 1;
+        size |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+}
+
+/// sizes of the source and of the result of a git delta
+pub(crate) fn delta_sizes(delta: &[u8]) -> Option<(usize, usize)> {
+    let mut pos = 0;
+    Some((delta_size(delta, &mut pos)?, delta_size(delta, &mut pos)?))
+}
+
+/// apply a git delta to `source`.
+///
+/// the delta starts with the sizes of the source and of the result, then
+/// holds instructions either copying a range of the source or inserting
+/// the bytes following them
+pub(crate) fn apply_delta(
+    source: &[u8],
+    delta: &[u8],
+) -> Result<Vec<u8>, &'static str> {
+    let mut pos = 0;
+    let source_size =
+        delta_size(delta, &mut pos).ok_or("truncated delta header")?;
+    let result_size =
+        delta_size(delta, &mut pos).ok_or("truncated delta header")?;
+    if source_size != source.len() {
+        return Err("source size does not match the delta");
+    }
+
+    let mut out = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let cmd = delta[pos];
+        pos += 1;
+        if cmd & 0x80 != 0 {
+            // copy, the low bits tell which offset and size bytes follow
+            let mut read = |bits: u8,
+                            shifts: &[usize]|
+             -> Result<usize, &str> {
+                let mut value = 0;
+                for (i, shift) in shifts.iter().enumerate() {
+                    if bits & (1 << i) != 0 {
+                        let byte = *delta.get(pos).ok_or("truncated copy")?;
+                        pos += 1;
+                        value |= (byte as usize) << shift;
+                    }
+                }
+                Ok(value)
+            };
+            let offset = read(cmd, &[0, 8, 16, 24])?;
+            let size = match read(cmd >> 4, &[0, 8, 16])? {
+                0 => 0x10000,
+                size => size,
+            };
+            let range = source
+                .get(offset..offset + size)
+                .ok_or("copy out of the source")?;
+            out.extend_from_slice(range);
+        } else if cmd != 0 {
+            let range = delta
+                .get(pos..pos + cmd as usize)
+                .ok_or("truncated insert")?;
+            out.extend_from_slice(range);
+            pos += cmd as usize;
+        } else {
+            return Err("unexpected delta opcode 0");
+        }
+    }
+    if out.len() != result_size {
+        return Err("result size does not match the delta");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base85_roundtrip() {
+        for data in [&b""[..], b"a", b"abcd", b"\x00\xff\x10 binary\x00"] {
+            let encoded = encode_base85(data);
+            assert_eq!(decode_base85(&encoded, data.len()).unwrap(), data);
+        }
+        // `literal 0` of git
+        let empty = decode_base85("cmV?d00001", 8).unwrap();
+        assert_eq!(inflate(&empty).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let source = b"hello binary world";
+        // sizes 18 -> 13, copy 6 bytes at 0, insert "dear ", copy 2 at 16
+        let mut delta = vec![18, 13, 0x90, 6, 5];
+        delta.extend_from_slice(b"dear ");
+        delta.extend_from_slice(&[0x91, 16, 2]);
+        assert_eq!(apply_delta(source, &delta).unwrap(), b"hello dear ld");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+}
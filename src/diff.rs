@@ -1,8 +1,11 @@
 use std::{
-    fs, io,
+    borrow::Cow,
+    fmt, fs, io,
     path::{Path, PathBuf},
 };
 
+use crate::{binary, transaction::Transaction, writer::quote_path};
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -12,16 +15,71 @@ pub const DIFF_SIGN_LINE_DEFAULT: &str = " ";
 pub const DIFF_SIGN_HEADER_ORIGIN: &str = "---";
 pub const DIFF_SIGN_HEADER_NEW: &str = "+++";
 pub const DIFF_SIGN_HUNK: &str = "@@";
+/// start of the old path and ranges in a context diff
+pub const DIFF_SIGN_CONTEXT_ORIGIN: &str = "***";
+/// line starting each hunk of a context diff
+pub const DIFF_SIGN_CONTEXT_SEPARATOR: &str = "***************";
+/// mark of a changed line in a context diff
+pub const DIFF_SIGN_LINE_CHANGED: &str = "!";
+pub const DIFF_SIGN_NO_NEWLINE: &str = "\\ No newline at end of file";
+pub const DIFF_SIGN_BINARY: &str = "GIT binary patch";
+pub const DIFF_SIGN_BINARY_STUB: &str = "Binary files ";
+/// path standing for a missing file, on the `---` or `+++` line
+pub const DEV_NULL: &str = "/dev/null";
+pub const DIFF_SIGN_CONFLICT_OURS: &str = "<<<<<<< ours";
+pub const DIFF_SIGN_CONFLICT_SEPARATOR: &str = "=======";
+pub const DIFF_SIGN_CONFLICT_THEIRS: &str = ">>>>>>> theirs";
+/// date ending the first line of each email of `git format-patch`
+pub const MAIL_FROM_DATE: &str = "Mon Sep 17 00:00:00 2001";
+/// line before the signature of an email
+pub const MAIL_SIGNATURE: &str = "-- ";
 
 #[cfg(feature = "serde")]
 #[derive(Serialize, Deserialize, Debug)]
 pub enum DiffFormat {
     GitUdiff,
+    /// unified format of `diff -u`, without the git headers
+    Unified,
+    /// context format of `diff -c`
+    Context,
+    /// default format of `diff`, without context
+    Normal,
+    /// ed script of `diff -e`
+    Ed,
 }
 #[cfg(not(feature = "serde"))]
 #[derive(Debug)]
 pub enum DiffFormat {
     GitUdiff,
+    /// unified format of `diff -u`, without the git headers
+    Unified,
+    /// context format of `diff -c`
+    Context,
+    /// default format of `diff`, without context
+    Normal,
+    /// ed script of `diff -e`
+    Ed,
+}
+
+/// algorithm used to generate a diff
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    Myers,
+    /// Myers' algorithm without the cost cutoffs, as `--minimal`
+    Minimal,
+    Patience,
+    Histogram,
+}
+/// algorithm used to generate a diff
+#[cfg(not(feature = "serde"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    Myers,
+    /// Myers' algorithm without the cost cutoffs, as `--minimal`
+    Minimal,
+    Patience,
+    Histogram,
 }
 
 #[cfg(feature = "serde")]
@@ -29,19 +87,95 @@ pub enum DiffFormat {
 pub struct DiffComposition {
     pub format: DiffFormat,
     pub diff: Vec<Diff>,
+    /// text before the first diff, such as a commit header, kept by the
+    /// lenient parser and written back in git format
+    pub preamble: String,
+    /// text between two diffs, such as the header of the next commit of
+    /// `git log -p`, with the index of the diff it comes before
+    pub interstitial: Vec<(usize, String)>,
+    /// text after the last diff, such as an email signature
+    pub trailer: String,
 }
 #[cfg(not(feature = "serde"))]
 #[derive(Debug)]
 pub struct DiffComposition {
     pub format: DiffFormat,
     pub diff: Vec<Diff>,
+    /// text before the first diff, such as a commit header, kept by the
+    /// lenient parser and written back in git format
+    pub preamble: String,
+    /// text between two diffs, such as the header of the next commit of
+    /// `git log -p`, with the index of the diff it comes before
+    pub interstitial: Vec<(usize, String)>,
+    /// text after the last diff, such as an email signature
+    pub trailer: String,
+}
+/// commit mailed by `git format-patch`
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Patch {
+    /// commit of the `From <commit> Mon Sep 17 00:00:00 2001` line
+    pub commit: Option<String>,
+    /// author as `Name <email>`
+    pub author: String,
+    /// date of the commit, as written in the email
+    pub date: String,
+    /// bracketed prefix of the subject, such as `PATCH 1/2`
+    pub prefix: Option<String>,
+    /// first line of the commit message
+    pub subject: String,
+    /// commit message after the subject, with its trailers
+    pub message: String,
+    pub diff: DiffComposition,
+    /// text after the `-- ` line, the version of git by default
+    pub signature: Option<String>,
+}
+/// commit mailed by `git format-patch`
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct Patch {
+    /// commit of the `From <commit> Mon Sep 17 00:00:00 2001` line
+    pub commit: Option<String>,
+    /// author as `Name <email>`
+    pub author: String,
+    /// date of the commit, as written in the email
+    pub date: String,
+    /// bracketed prefix of the subject, such as `PATCH 1/2`
+    pub prefix: Option<String>,
+    /// first line of the commit message
+    pub subject: String,
+    /// commit message after the subject, with its trailers
+    pub message: String,
+    pub diff: DiffComposition,
+    /// text after the `-- ` line, the version of git by default
+    pub signature: Option<String>,
 }
 #[cfg(feature = "serde")]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Diff {
     pub command: Option<String>,
     pub index: Option<String>, // TODO: type this
+    /// path of the file before the diff, differs from `path` when the file
+    /// is renamed or copied
+    pub old_path: PathBuf,
+    /// path of the file after the diff
     pub path: PathBuf,
+    /// text after the old path in a diff not made by git, usually the
+    /// modification time of the file
+    pub old_timestamp: Option<String>,
+    /// text after the new path in a diff not made by git
+    pub new_timestamp: Option<String>,
+    pub status: FileStatus,
+    /// unix mode of the file before the diff, when recorded
+    pub old_mode: Option<u32>,
+    /// unix mode of the file after the diff, when recorded
+    pub new_mode: Option<u32>,
+    /// similarity of a renamed or copied file with its source, in percent
+    pub similarity: Option<u8>,
+    /// dissimilarity of a rewritten file with its old content, in percent
+    pub dissimilarity: Option<u8>,
+    /// content of a binary file, which has no hunks
+    pub binary: Option<BinaryPatch>,
     pub hunk: Vec<DiffHunk>,
 }
 #[cfg(not(feature = "serde"))]
@@ -49,9 +183,101 @@ pub struct Diff {
 pub struct Diff {
     pub command: Option<String>,
     pub index: Option<String>, // TODO: type this
+    /// path of the file before the diff, differs from `path` when the file
+    /// is renamed or copied
+    pub old_path: PathBuf,
+    /// path of the file after the diff
     pub path: PathBuf,
+    /// text after the old path in a diff not made by git, usually the
+    /// modification time of the file
+    pub old_timestamp: Option<String>,
+    /// text after the new path in a diff not made by git
+    pub new_timestamp: Option<String>,
+    pub status: FileStatus,
+    /// unix mode of the file before the diff, when recorded
+    pub old_mode: Option<u32>,
+    /// unix mode of the file after the diff, when recorded
+    pub new_mode: Option<u32>,
+    /// similarity of a renamed or copied file with its source, in percent
+    pub similarity: Option<u8>,
+    /// dissimilarity of a rewritten file with its old content, in percent
+    pub dissimilarity: Option<u8>,
+    /// content of a binary file, which has no hunks
+    pub binary: Option<BinaryPatch>,
     pub hunk: Vec<DiffHunk>,
 }
+/// content of a binary diff
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub enum BinaryPatch {
+    /// `Binary files ... differ`, without the content to apply
+    Stub,
+    /// `GIT binary patch`, with the hunk to apply and the one to revert
+    Git {
+        forward: BinaryHunk,
+        reverse: Option<BinaryHunk>,
+    },
+}
+/// content of a binary diff
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub enum BinaryPatch {
+    /// `Binary files ... differ`, without the content to apply
+    Stub,
+    /// `GIT binary patch`, with the hunk to apply and the one to revert
+    Git {
+        forward: BinaryHunk,
+        reverse: Option<BinaryHunk>,
+    },
+}
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BinaryHunk {
+    pub kind: BinaryKind,
+    /// inflated data, the whole content for a literal and the instructions
+    /// building it from the target for a delta
+    pub data: Vec<u8>,
+}
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct BinaryHunk {
+    pub kind: BinaryKind,
+    /// inflated data, the whole content for a literal and the instructions
+    /// building it from the target for a delta
+    pub data: Vec<u8>,
+}
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryKind {
+    Literal,
+    Delta,
+}
+#[cfg(not(feature = "serde"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryKind {
+    Literal,
+    Delta,
+}
+/// what a diff does to its file
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+    Copied,
+}
+/// what a diff does to its file
+#[cfg(not(feature = "serde"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+    Copied,
+}
 #[cfg(feature = "serde")]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DiffHunk {
@@ -59,6 +285,8 @@ pub struct DiffHunk {
     pub old_len: usize,
     pub new_line: usize,
     pub new_len: usize,
+    /// section heading following the range, such as the enclosing function
+    pub heading: Option<String>,
     pub change: Vec<LineChange>,
 }
 #[cfg(not(feature = "serde"))]
@@ -68,56 +296,169 @@ pub struct DiffHunk {
     pub old_len: usize,
     pub new_line: usize,
     pub new_len: usize,
+    /// section heading following the range, such as the enclosing function
+    pub heading: Option<String>,
     pub change: Vec<LineChange>,
 }
 #[cfg(feature = "serde")]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LineChange {
     pub kind: Change,
-    pub content: String,
+    /// bytes of the line without its newline, in the encoding of the file
+    pub content: Vec<u8>,
+    /// the line ends the file without a newline,
+    /// marked by `\ No newline at end of file`
+    pub no_newline: bool,
 }
 
 #[cfg(not(feature = "serde"))]
 #[derive(Debug)]
 pub struct LineChange {
     pub kind: Change,
-    pub content: String,
+    /// bytes of the line without its newline, in the encoding of the file
+    pub content: Vec<u8>,
+    /// the line ends the file without a newline,
+    /// marked by `\ No newline at end of file`
+    pub no_newline: bool,
 }
 #[cfg(feature = "serde")]
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Change {
     Default,
     Added,
     Deleted,
 }
 #[cfg(not(feature = "serde"))]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Change {
     Default,
     Added,
     Deleted,
 }
 
+/// diffs of a merge against each of its parents, of `git diff --cc`
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CombinedComposition {
+    pub diff: Vec<CombinedDiff>,
+}
+/// diffs of a merge against each of its parents, of `git diff --cc`
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct CombinedComposition {
+    pub diff: Vec<CombinedDiff>,
+}
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CombinedDiff {
+    pub command: Option<String>,
+    /// objects of the file in each parent then in the merge, as `a,b..c`
+    pub index: Option<String>,
+    pub path: PathBuf,
+    /// `Added` or `Deleted` when the file is in none of the parents or
+    /// not in the merge
+    pub status: FileStatus,
+    /// mode of the file in each parent, empty when not given
+    pub old_mode: Vec<u32>,
+    pub new_mode: Option<u32>,
+    pub hunk: Vec<CombinedHunk>,
+}
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct CombinedDiff {
+    pub command: Option<String>,
+    /// objects of the file in each parent then in the merge, as `a,b..c`
+    pub index: Option<String>,
+    pub path: PathBuf,
+    /// `Added` or `Deleted` when the file is in none of the parents or
+    /// not in the merge
+    pub status: FileStatus,
+    /// mode of the file in each parent, empty when not given
+    pub old_mode: Vec<u32>,
+    pub new_mode: Option<u32>,
+    pub hunk: Vec<CombinedHunk>,
+}
+/// hunk of a combined diff, as `@@@ -1,3 -1,4 +1,5 @@@`
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CombinedHunk {
+    /// start and length of the range in each parent
+    pub old_range: Vec<(usize, usize)>,
+    pub new_line: usize,
+    pub new_len: usize,
+    pub heading: Option<String>,
+    pub change: Vec<CombinedChange>,
+}
+/// hunk of a combined diff, as `@@@ -1,3 -1,4 +1,5 @@@`
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct CombinedHunk {
+    /// start and length of the range in each parent
+    pub old_range: Vec<(usize, usize)>,
+    pub new_line: usize,
+    pub new_len: usize,
+    pub heading: Option<String>,
+    pub change: Vec<CombinedChange>,
+}
+/// line of a combined hunk, with a column of signs for the parents
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CombinedChange {
+    /// change against each parent. a line of the merge is `Added` where
+    /// a parent lacks it, a line missing from the merge is `Deleted`
+    /// where a parent has it. other columns are `Default`
+    pub kind: Vec<Change>,
+    pub content: Vec<u8>,
+    pub no_newline: bool,
+}
+/// line of a combined hunk, with a column of signs for the parents
+#[cfg(not(feature = "serde"))]
+#[derive(Debug)]
+pub struct CombinedChange {
+    /// change against each parent. a line of the merge is `Added` where
+    /// a parent lacks it, a line missing from the merge is `Deleted`
+    /// where a parent has it. other columns are `Default`
+    pub kind: Vec<Change>,
+    pub content: Vec<u8>,
+    pub no_newline: bool,
+}
+
 #[cfg(feature = "serde")]
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Line {
     Command,
+    /// git extended header, such as `new file mode`
+    ExtendedHeader,
     Index,
     OrignPath,
     NewPath,
     Hunk,
+    /// `GIT binary patch` or `Binary files ... differ`
+    Binary,
+    /// line of the hunks of a binary patch
+    BinaryData,
     LineChange(Change),
+    /// `\ No newline at end of file`
+    NoNewline,
     Unknown,
 }
 #[cfg(not(feature = "serde"))]
 #[derive(Debug)]
 pub enum Line {
     Command,
+    /// git extended header, such as `new file mode`
+    ExtendedHeader,
     Index,
     OrignPath,
     NewPath,
     Hunk,
+    /// `GIT binary patch` or `Binary files ... differ`
+    Binary,
+    /// line of the hunks of a binary patch
+    BinaryData,
     LineChange(Change),
+    /// `\ No newline at end of file`
+    NoNewline,
     Unknown,
 }
 
@@ -130,7 +471,70 @@ pub struct DiffError {
 pub enum DiffErrorKind {
     IOError(io::Error),
     InvalidIndex(usize),
-    UnmatchedContent(String, String),
+    /// content of the target does not match the hunk.
+    /// `hunk` is the index of the hunk in the diff,
+    /// `line` is the 1-based line number in the target.
+    /// lines which are not UTF-8 are converted lossily
+    UnmatchedContent {
+        hunk: usize,
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+    /// the file to create already exists
+    AlreadyExists(PathBuf),
+    /// the file to delete is not empty once patched
+    NotEmpty(PathBuf),
+    /// the diff has no content to apply, such as a binary diff stub
+    NotApplicable(PathBuf),
+    /// a binary delta does not fit the target
+    InvalidDelta,
+}
+
+impl DiffError {
+    pub fn kind(&self) -> &DiffErrorKind {
+        &self.kind
+    }
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// copy of the error, io errors only keep their kind and message
+    fn duplicate(&self) -> DiffError {
+        let kind = match &self.kind {
+            DiffErrorKind::IOError(e) => {
+                DiffErrorKind::IOError(io::Error::new(e.kind(), e.to_string()))
+            }
+            DiffErrorKind::InvalidIndex(idx) => {
+                DiffErrorKind::InvalidIndex(*idx)
+            }
+            DiffErrorKind::UnmatchedContent {
+                hunk,
+                line,
+                expected,
+                actual,
+            } => DiffErrorKind::UnmatchedContent {
+                hunk: *hunk,
+                line: *line,
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+            DiffErrorKind::AlreadyExists(path) => {
+                DiffErrorKind::AlreadyExists(path.clone())
+            }
+            DiffErrorKind::NotEmpty(path) => {
+                DiffErrorKind::NotEmpty(path.clone())
+            }
+            DiffErrorKind::NotApplicable(path) => {
+                DiffErrorKind::NotApplicable(path.clone())
+            }
+            DiffErrorKind::InvalidDelta => DiffErrorKind::InvalidDelta,
+        };
+        DiffError {
+            kind,
+            reason: self.reason.clone(),
+        }
+    }
 }
 
 impl From<io::Error> for DiffError {
@@ -142,137 +546,1265 @@ impl From<io::Error> for DiffError {
     }
 }
 
+/// options used to place hunks on the target
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// max number of leading and trailing context lines that can be ignored
+    /// when the hunk cannot be placed with its full context
+    pub fuzz: usize,
+    /// max distance (in lines) from the recorded line to search for the
+    /// hunk. `None` to search the whole target
+    pub max_offset: Option<usize>,
+    /// match lines differing only by a carriage return at their end, like
+    /// `git apply --ignore-whitespace`. the lines of the target are kept
+    /// as they are and added lines take its most common line ending
+    pub ignore_cr_at_eol: bool,
+}
+
+/// where a hunk was placed on the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HunkPlacement {
+    /// index of the hunk in the diff
+    pub hunk: usize,
+    /// distance from the recorded line to the line the hunk was placed at
+    pub offset: isize,
+    /// number of context lines ignored at each end of the hunk
+    pub fuzz: usize,
+}
+
+/// hunk written as a conflict region by a merge apply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub path: PathBuf,
+    /// index of the hunk in the diff
+    pub hunk: usize,
+    /// 1-based line of the `<<<<<<<` marker in the merged file
+    pub line: usize,
+}
+
+/// hunk which was not applied
+#[derive(Debug)]
+pub struct HunkRejection {
+    /// index of the hunk in the diff
+    pub hunk: usize,
+    pub error: DiffError,
+}
+
+#[derive(Debug)]
+pub struct ApplyReport {
+    pub path: PathBuf,
+    pub hunks: Vec<HunkPlacement>,
+    pub conflicts: Vec<MergeConflict>,
+    pub rejected: Vec<HunkRejection>,
+}
+
+/// result of a dry run over a composition
+#[derive(Debug)]
+pub struct CheckReport {
+    pub files: Vec<ApplyReport>,
+}
+
+impl CheckReport {
+    /// true when every hunk of every file can be placed
+    pub fn is_ok(&self) -> bool {
+        self.files.iter().all(|file| file.rejected.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Apply,
+    Revert,
+}
+
+/// what patching a diff in a direction does to its files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileOp {
+    Modify,
+    Create,
+    Delete,
+    Move,
+    Copy,
+    /// remove a copy, whatever is left of it
+    Uncopy,
+}
+
+/// what to do with a hunk which cannot be placed
+#[derive(Debug, Clone, Copy)]
+enum Unplaced {
+    Fail,
+    Conflict,
+    Reject,
+}
+
 impl DiffComposition {
+    /// apply every diff. nothing is written unless every diff applies,
+    /// and the files are replaced all together
     pub fn apply(&self, root: &Path) -> Result<(), DiffError> {
-        for diff in &self.diff {
-            let target_path = root.join(&diff.path);
-            let original = fs::read_to_string(&target_path)?;
-            let after = diff.apply(&original)?;
-            fs::write(target_path, after)?;
-        }
+        self.apply_with(root, &ApplyOptions::default())?;
         Ok(())
     }
     pub fn revert(&self, root: &Path) -> Result<(), DiffError> {
+        self.revert_with(root, &ApplyOptions::default())?;
+        Ok(())
+    }
+    pub fn apply_with(
+        &self,
+        root: &Path,
+        options: &ApplyOptions,
+    ) -> Result<Vec<ApplyReport>, DiffError> {
+        self.patch_all(root, Direction::Apply, options)
+    }
+    pub fn revert_with(
+        &self,
+        root: &Path,
+        options: &ApplyOptions,
+    ) -> Result<Vec<ApplyReport>, DiffError> {
+        self.patch_all(root, Direction::Revert, options)
+    }
+    fn patch_all(
+        &self,
+        root: &Path,
+        direction: Direction,
+        options: &ApplyOptions,
+    ) -> Result<Vec<ApplyReport>, DiffError> {
+        // each diff patches the files as left by the previous ones, which
+        // are the next ones when reverting
+        let mut transaction = Transaction::new();
+        let mut reports = Vec::new();
+        for diff in self.in_order(direction) {
+            let (after, report) = diff.patch_target(
+                root,
+                direction,
+                options,
+                Unplaced::Fail,
+                &transaction,
+            )?;
+            if diff.operation(direction) == FileOp::Delete && !after.is_empty()
+            {
+                let path = diff
+                    .paths_in(root, direction, &transaction)
+                    .0
+                    .to_path_buf();
+                Err(DiffError {
+                    reason: format!(
+                        "{} is not empty once patched",
+                        path.display()
+                    ),
+                    kind: DiffErrorKind::NotEmpty(path),
+                })?;
+            }
+            diff.stage(&mut transaction, root, direction, after);
+            reports.push(report);
+        }
+        transaction.commit()?;
+        if let Direction::Revert = direction {
+            reports.reverse();
+        }
+        Ok(reports)
+    }
+    /// diffs in the order they are patched in `direction`
+    fn in_order(&self, direction: Direction) -> Vec<&Diff> {
+        match direction {
+            Direction::Apply => self.diff.iter().collect(),
+            Direction::Revert => self.diff.iter().rev().collect(),
+        }
+    }
+    /// apply every hunk which can be placed, and write the rejected hunks of
+    /// each file to `<path>.rej`
+    pub fn apply_partial(
+        &self,
+        root: &Path,
+        options: &ApplyOptions,
+    ) -> Result<Vec<ApplyReport>, DiffError> {
+        let mut transaction = Transaction::new();
+        let mut reports = Vec::new();
+        // rejected hunks of each patched path, empty when all applied
+        let mut rejects: Vec<(PathBuf, String)> = Vec::new();
+        for diff in &self.diff {
+            let mut reject_path = root.join(&diff.path).into_os_string();
+            reject_path.push(".rej");
+            let (after, report) =
+                diff.patch_file(root, Direction::Apply, options, &transaction);
+            if let Some(after) = after {
+                if !report.hunks.is_empty() || diff.hunk.is_empty() {
+                    diff.stage(&mut transaction, root, Direction::Apply, after);
+                }
+            }
+            let reject = match report.rejected.is_empty() {
+                true => String::new(),
+                false => diff.reject(&report),
+            };
+            let reject_path = PathBuf::from(reject_path);
+            match rejects.iter_mut().find(|(path, _)| *path == reject_path) {
+                Some((_, rejected)) => rejected.push_str(&reject),
+                None => rejects.push((reject_path, reject)),
+            }
+            reports.push(report);
+        }
+        // a `.rej` left by an earlier run would look like a new rejection
+        for (path, reject) in rejects {
+            if !reject.is_empty() {
+                transaction.write(path, reject);
+            } else if transaction.exists(&path) {
+                transaction.remove(path);
+            }
+        }
+        transaction.commit()?;
+        Ok(reports)
+    }
+    /// check whether every diff can be applied, without writing anything
+    pub fn check(&self, root: &Path) -> CheckReport {
+        self.dry_run(root, Direction::Apply)
+    }
+    /// check whether every diff can be reverted, without writing anything
+    pub fn check_revert(&self, root: &Path) -> CheckReport {
+        self.dry_run(root, Direction::Revert)
+    }
+    /// patch the diffs in memory in the order of a real patch, each one on
+    /// the files left by the previous ones. nothing is committed
+    fn dry_run(&self, root: &Path, direction: Direction) -> CheckReport {
+        let options = ApplyOptions::default();
+        let mut files = Transaction::new();
+        let mut reports = Vec::new();
+        for diff in self.in_order(direction) {
+            let (after, report) =
+                diff.patch_file(root, direction, &options, &files);
+            if let Some(after) = after {
+                diff.stage(&mut files, root, direction, after);
+            }
+            reports.push(report);
+        }
+        if let Direction::Revert = direction {
+            reports.reverse();
+        }
+        CheckReport { files: reports }
+    }
+    /// apply every diff, writing conflict markers for the hunks which
+    /// cannot be placed. returns the conflicted hunks
+    pub fn merge(
+        &self,
+        root: &Path,
+        options: &ApplyOptions,
+    ) -> Result<Vec<MergeConflict>, DiffError> {
+        let mut transaction = Transaction::new();
+        let mut conflicts = Vec::new();
         for diff in &self.diff {
-            let target_path = root.join(&diff.path);
-            let applied = fs::read_to_string(&target_path)?;
-            let after = diff.revert(&applied)?;
-            fs::write(target_path, after)?;
+            let (merged, report) = diff.patch_target(
+                root,
+                Direction::Apply,
+                options,
+                Unplaced::Conflict,
+                &transaction,
+            )?;
+            diff.stage(&mut transaction, root, Direction::Apply, merged);
+            conflicts.extend(report.conflicts);
+        }
+        transaction.commit()?;
+        Ok(conflicts)
+    }
+    /// add context to the hunks of every diff, read from the old files
+    /// under `root`, see `Diff::add_context`. a diff of a single file
+    /// without paths reads `root` itself
+    pub fn add_context(
+        &mut self,
+        root: &Path,
+        lines: usize,
+    ) -> Result<(), DiffError> {
+        for diff in &mut self.diff {
+            if diff.hunk.is_empty() || diff.status == FileStatus::Added {
+                continue;
+            }
+            let old = match diff.old_path.as_os_str().is_empty() {
+                true => fs::read(root)?,
+                false => fs::read(root.join(&diff.old_path))?,
+            };
+            diff.add_context(&old, lines)?;
         }
         Ok(())
     }
 }
 
+/// content which is not UTF-8 is written lossily, see `DiffHunk::to_bytes`
+impl fmt::Display for DiffHunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+/// content which is not UTF-8 is written lossily, see `LineChange::to_bytes`
+impl fmt::Display for LineChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+impl LineChange {
+    /// the line as written in a hunk, without its last newline
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sign = match self.kind {
+            Change::Default => DIFF_SIGN_LINE_DEFAULT,
+            Change::Added => DIFF_SIGN_LINE_ADDED,
+            Change::Deleted => DIFF_SIGN_LINE_DELETED,
+        };
+        let mut out = Vec::with_capacity(self.content.len() + 1);
+        out.extend_from_slice(sign.as_bytes());
+        out.extend_from_slice(&self.content);
+        if self.no_newline {
+            out.push(b'\n');
+            out.extend_from_slice(DIFF_SIGN_NO_NEWLINE.as_bytes());
+        }
+        out
+    }
+}
+
+impl DiffHunk {
+    /// the hunk line, with the ranges and the heading
+    pub fn header(&self) -> String {
+        // the length of a range is omitted when it is 1
+        let range = |line: usize, len: usize| match len {
+            1 => format!("{line}"),
+            _ => format!("{line},{len}"),
+        };
+        let header = format!(
+            "{DIFF_SIGN_HUNK} -{} +{} {DIFF_SIGN_HUNK}",
+            range(self.old_line, self.old_len),
+            range(self.new_line, self.new_len)
+        );
+        match &self.heading {
+            Some(heading) => format!("{header} {heading}"),
+            None => header,
+        }
+    }
+
+    /// the hunk as written in a diff, keeping the bytes of its lines
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header().into_bytes();
+        out.push(b'\n');
+        for change in &self.change {
+            out.extend(change.to_bytes());
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// lines expected on the target (preimage) and
+    /// lines replacing them (postimage)
+    fn images(&self, direction: Direction) -> (Vec<&[u8]>, Vec<&[u8]>) {
+        let (remove, add) = match direction {
+            Direction::Apply => (Change::Deleted, Change::Added),
+            Direction::Revert => (Change::Added, Change::Deleted),
+        };
+        let mut pre = Vec::new();
+        let mut post = Vec::new();
+        for change in &self.change {
+            if change.kind != add {
+                pre.push(change.content.as_slice());
+            }
+            if change.kind != remove {
+                post.push(change.content.as_slice());
+            }
+        }
+        (pre, post)
+    }
+
+    /// whether the last line of the preimage and of the postimage end
+    /// the file without a newline
+    fn no_newline(&self, direction: Direction) -> (bool, bool) {
+        let (remove, add) = match direction {
+            Direction::Apply => (Change::Deleted, Change::Added),
+            Direction::Revert => (Change::Added, Change::Deleted),
+        };
+        let last = |skip: Change| {
+            self.change
+                .iter()
+                .rev()
+                .find(|change| change.kind != skip)
+                .is_some_and(|change| change.no_newline)
+        };
+        (last(add), last(remove))
+    }
+
+    /// postimage replacing the `target` lines matched by the preimage, less
+    /// the `lead` and `trail` context lines ignored by fuzz. context lines
+    /// are taken from the target. with `cr`, added lines end with a
+    /// carriage return or not
+    fn postimage<'a>(
+        &'a self,
+        direction: Direction,
+        target: &[&'a [u8]],
+        (lead, trail): (usize, usize),
+        cr: Option<bool>,
+    ) -> Vec<Cow<'a, [u8]>> {
+        let (remove, add) = match direction {
+            Direction::Apply => (Change::Deleted, Change::Added),
+            Direction::Revert => (Change::Added, Change::Deleted),
+        };
+        let mut matched = target.iter();
+        let mut post = Vec::with_capacity(target.len());
+        for change in &self.change[lead..self.change.len() - trail] {
+            let content = change.content.as_slice();
+            if change.kind == add {
+                let bare = content.strip_suffix(b"\r").unwrap_or(content);
+                post.push(match cr {
+                    Some(true) if bare == content => {
+                        Cow::Owned([content, b"\r"].concat())
+                    }
+                    Some(false) => Cow::Borrowed(bare),
+                    _ => Cow::Borrowed(content),
+                });
+                continue;
+            }
+            let Some(line) = matched.next() else {
+                break;
+            };
+            if change.kind != remove {
+                post.push(Cow::Borrowed(*line));
+            }
+        }
+        post
+    }
+
+    /// index of the first preimage line on the target.
+    /// an empty range starts after its line, not on it
+    fn start(&self, direction: Direction) -> usize {
+        let (line, len) = match direction {
+            Direction::Apply => (self.old_line, self.old_len),
+            Direction::Revert => (self.new_line, self.new_len),
+        };
+        // only an empty range should start at line 0
+        if len == 0 {
+            line
+        } else {
+            line.saturating_sub(1)
+        }
+    }
+
+    /// number of leading and trailing context lines
+    fn context(&self) -> (usize, usize) {
+        let is_default = |c: &&LineChange| c.kind == Change::Default;
+        let leading = self.change.iter().take_while(is_default).count();
+        let trailing = self.change[leading..]
+            .iter()
+            .rev()
+            .take_while(is_default)
+            .count();
+        (leading, trailing)
+    }
+}
+
+impl BinaryPatch {
+    /// patch the content of a binary file
+    fn patch(
+        &self,
+        target: &[u8],
+        direction: Direction,
+        path: &Path,
+    ) -> Result<Vec<u8>, DiffError> {
+        let not_applicable = |reason: &str| DiffError {
+            kind: DiffErrorKind::NotApplicable(path.to_path_buf()),
+            reason: format!("{}: {reason}", path.display()),
+        };
+        let hunk = match (self, direction) {
+            (BinaryPatch::Stub, _) => {
+                return Err(not_applicable("binary diff without content"));
+            }
+            (BinaryPatch::Git { forward, .. }, Direction::Apply) => forward,
+            (BinaryPatch::Git { reverse, .. }, Direction::Revert) => reverse
+                .as_ref()
+                .ok_or_else(|| not_applicable("binary patch has no reverse"))?,
+        };
+        match hunk.kind {
+            BinaryKind::Literal => Ok(hunk.data.clone()),
+            BinaryKind::Delta => binary::apply_delta(target, &hunk.data)
+                .map_err(|reason| DiffError {
+                    kind: DiffErrorKind::InvalidDelta,
+                    reason: format!("{}: {reason}", path.display()),
+                }),
+        }
+    }
+}
+
 impl Diff {
     pub fn apply(&self, original: &str) -> Result<String, DiffError> {
-        let mut buffer = String::new();
-
-        // index of original line
-        let mut oidx: usize = 0;
-        let lines: Vec<&str> = original.lines().collect();
-
-        for hunk in &self.hunk {
-            while oidx < (hunk.old_line - 1) {
-                buffer.push_str(lines.get(oidx).ok_or_else(|| DiffError {
-                    kind: DiffErrorKind::InvalidIndex(oidx),
-                    reason: format!("cannot get line at {oidx}"),
-                })?);
-                buffer.push('\n');
-                oidx += 1;
-            }
-            for change in &hunk.change {
-                match change.kind {
-                    Change::Default => {
-                        buffer.push_str(lines.get(oidx).ok_or_else(|| {
-                            DiffError {
-                                kind: DiffErrorKind::InvalidIndex(oidx),
-                                reason: format!("cannot get line at {oidx}"),
-                            }
-                        })?);
-                        buffer.push('\n');
-                        oidx += 1;
-                    }
-                    Change::Deleted => {
-                        oidx += 1;
-                        continue;
-                    }
-                    Change::Added => {
-                        buffer.push_str(&change.content);
-                        buffer.push('\n');
-                    }
+        Ok(self.apply_with(original, &ApplyOptions::default())?.0)
+    }
+
+    pub fn revert(&self, applied: &str) -> Result<String, DiffError> {
+        Ok(self.revert_with(applied, &ApplyOptions::default())?.0)
+    }
+
+    pub fn apply_with(
+        &self,
+        original: &str,
+        options: &ApplyOptions,
+    ) -> Result<(String, ApplyReport), DiffError> {
+        self.patch_str(original, Direction::Apply, options, Unplaced::Fail)
+    }
+
+    pub fn revert_with(
+        &self,
+        applied: &str,
+        options: &ApplyOptions,
+    ) -> Result<(String, ApplyReport), DiffError> {
+        self.patch_str(applied, Direction::Revert, options, Unplaced::Fail)
+    }
+
+    /// apply the diff to the bytes of a file, whatever its encoding
+    pub fn apply_bytes(
+        &self,
+        original: &[u8],
+        options: &ApplyOptions,
+    ) -> Result<(Vec<u8>, ApplyReport), DiffError> {
+        self.patch(original, Direction::Apply, options, Unplaced::Fail)
+    }
+
+    /// revert the diff on the bytes of a file, whatever its encoding
+    pub fn revert_bytes(
+        &self,
+        applied: &[u8],
+        options: &ApplyOptions,
+    ) -> Result<(Vec<u8>, ApplyReport), DiffError> {
+        self.patch(applied, Direction::Revert, options, Unplaced::Fail)
+    }
+
+    /// apply every hunk which can be placed, reporting the others as
+    /// rejected instead of failing
+    pub fn apply_partial(
+        &self,
+        original: &str,
+        options: &ApplyOptions,
+    ) -> Result<(String, ApplyReport), DiffError> {
+        self.patch_str(original, Direction::Apply, options, Unplaced::Reject)
+    }
+
+    /// patch the file of this diff under `root` in memory, rejecting the
+    /// hunks which cannot be placed.
+    /// returns `None` as content when the file cannot be read
+    fn patch_file(
+        &self,
+        root: &Path,
+        direction: Direction,
+        options: &ApplyOptions,
+        files: &Transaction,
+    ) -> (Option<Vec<u8>>, ApplyReport) {
+        match self.patch_target(
+            root,
+            direction,
+            options,
+            Unplaced::Reject,
+            files,
+        ) {
+            Ok((after, report)) => (Some(after), report),
+            Err(e) => {
+                // a binary patch is a single hunk
+                let hunks = match self.binary {
+                    Some(_) => 1,
+                    None => self.hunk.len(),
+                };
+                let report = ApplyReport {
+                    path: self.path.clone(),
+                    hunks: Vec::new(),
+                    conflicts: Vec::new(),
+                    rejected: (0..hunks)
+                        .map(|hunk| HunkRejection {
+                            hunk,
+                            error: e.duplicate(),
+                        })
+                        .collect(),
+                };
+                (None, report)
+            }
+        }
+    }
+
+    /// patch the file of this diff under `root` in memory, as left by the
+    /// writes queued in `files`. a binary patch is reported as hunk 0
+    fn patch_target(
+        &self,
+        root: &Path,
+        direction: Direction,
+        options: &ApplyOptions,
+        unplaced: Unplaced,
+        files: &Transaction,
+    ) -> Result<(Vec<u8>, ApplyReport), DiffError> {
+        let target = self.read_target(root, direction, files)?;
+        if let Some(binary) = &self.binary {
+            let after = binary.patch(&target, direction, &self.path)?;
+            let report = ApplyReport {
+                path: self.path.clone(),
+                hunks: vec![HunkPlacement {
+                    hunk: 0,
+                    offset: 0,
+                    fuzz: 0,
+                }],
+                conflicts: Vec::new(),
+                rejected: Vec::new(),
+            };
+            return Ok((after, report));
+        }
+        self.patch(&target, direction, options, unplaced)
+    }
+
+    /// what the diff does to the files when patched in `direction`
+    fn operation(&self, direction: Direction) -> FileOp {
+        match (self.status, direction) {
+            (FileStatus::Modified, _) => FileOp::Modify,
+            (FileStatus::Renamed, _) => FileOp::Move,
+            (FileStatus::Added, Direction::Apply)
+            | (FileStatus::Deleted, Direction::Revert) => FileOp::Create,
+            (FileStatus::Deleted, Direction::Apply)
+            | (FileStatus::Added, Direction::Revert) => FileOp::Delete,
+            (FileStatus::Copied, Direction::Apply) => FileOp::Copy,
+            (FileStatus::Copied, Direction::Revert) => FileOp::Uncopy,
+        }
+    }
+
+    /// path the file is read from and path it is written to when patched
+    /// in `direction`. a modified file with two names, as `diff -u` writes
+    /// `--- main.rs.orig` and `+++ main.rs`, is patched in place under the
+    /// new name, or the old one when only this one exists
+    fn paths_in(
+        &self,
+        root: &Path,
+        direction: Direction,
+        files: &Transaction,
+    ) -> (&Path, &Path) {
+        if self.operation(direction) == FileOp::Modify {
+            let path = match files.exists(&root.join(&self.path))
+                || !files.exists(&root.join(&self.old_path))
+            {
+                true => &self.path,
+                false => &self.old_path,
+            };
+            return (path, path);
+        }
+        match direction {
+            Direction::Apply => (&self.old_path, &self.path),
+            Direction::Revert => (&self.path, &self.old_path),
+        }
+    }
+
+    /// mode of the file once patched in `direction`, when it changes
+    fn mode_in(&self, direction: Direction) -> Option<u32> {
+        match direction {
+            Direction::Apply => self.new_mode,
+            Direction::Revert => self.old_mode,
+        }
+    }
+
+    /// content of the file under `root` before patching in `direction`.
+    /// a file to create, move or copy to must not exist yet. a created
+    /// file starts empty
+    fn read_target(
+        &self,
+        root: &Path,
+        direction: Direction,
+        files: &Transaction,
+    ) -> Result<Vec<u8>, DiffError> {
+        let (from, to) = self.paths_in(root, direction, files);
+        let operation = self.operation(direction);
+        if matches!(operation, FileOp::Create | FileOp::Move | FileOp::Copy)
+            && files.exists(&root.join(to))
+        {
+            return Err(DiffError {
+                reason: format!("{} already exists", to.display()),
+                kind: DiffErrorKind::AlreadyExists(to.to_path_buf()),
+            });
+        }
+        if operation == FileOp::Create {
+            return Ok(Vec::new());
+        }
+        Ok(files.read(&root.join(from))?)
+    }
+
+    /// queue the patched content of the files under `root`. a deleted file
+    /// is only removed when nothing is left
+    fn stage(
+        &self,
+        transaction: &mut Transaction,
+        root: &Path,
+        direction: Direction,
+        after: Vec<u8>,
+    ) {
+        let (from, to) = self.paths_in(root, direction, transaction);
+        let (from, to) = (root.join(from), root.join(to));
+        let mode = self.mode_in(direction);
+        match self.operation(direction) {
+            FileOp::Delete if after.is_empty() => transaction.remove(from),
+            FileOp::Uncopy => transaction.remove(from),
+            FileOp::Modify | FileOp::Create | FileOp::Delete => {
+                transaction.write_mode(to, after, mode)
+            }
+            FileOp::Copy => transaction.write_copy(from, to, after, mode),
+            FileOp::Move => {
+                transaction.write_copy(&from, to, after, mode);
+                transaction.remove(from);
+            }
+        }
+    }
+
+    /// write the rejected hunks of `report` in unified format. a binary
+    /// patch has no hunks, the whole diff is written in git format
+    pub fn reject(&self, report: &ApplyReport) -> String {
+        if self.binary.is_some() {
+            return self.to_string();
+        }
+        let mut buffer = format!(
+            "{DIFF_SIGN_HEADER_ORIGIN} a/{}\n{DIFF_SIGN_HEADER_NEW} b/{}\n",
+            self.old_path.display(),
+            self.path.display()
+        );
+        // hunks rejected by index, an index out of the hunks is not one
+        debug_assert!(report.rejected.iter().all(|r| r.hunk < self.hunk.len()));
+        let rejected = self.hunk.iter().enumerate().filter(|(i, _)| {
+            report.rejected.iter().any(|rejection| rejection.hunk == *i)
+        });
+        for (_, hunk) in rejected {
+            buffer.push_str(&hunk.to_string());
+        }
+        buffer
+    }
+
+    /// apply the diff, writing conflict markers for the hunks which
+    /// cannot be placed instead of failing
+    pub fn merge(
+        &self,
+        original: &str,
+        options: &ApplyOptions,
+    ) -> Result<(String, Vec<MergeConflict>), DiffError> {
+        let (merged, report) = self.patch_str(
+            original,
+            Direction::Apply,
+            options,
+            Unplaced::Conflict,
+        )?;
+        Ok((merged, report.conflicts))
+    }
+
+    /// add up to `lines` lines of context around the hunks, read from
+    /// `old`, the file before the diff. hunks whose context would meet are
+    /// merged, as `diff -U` does. this recovers the context of formats
+    /// without any, such as the default format of `diff`
+    pub fn add_context(
+        &mut self,
+        old: &[u8],
+        lines: usize,
+    ) -> Result<(), DiffError> {
+        let mut target: Vec<&[u8]> = old.split(|b| *b == b'\n').collect();
+        let no_newline = !old.is_empty() && !old.ends_with(b"\n");
+        if !no_newline {
+            target.pop();
+        }
+        // the hunks to merge, with the range of their old lines
+        let mut groups: Vec<Vec<(usize, usize, DiffHunk)>> = Vec::new();
+        for (idx, hunk) in
+            std::mem::take(&mut self.hunk).into_iter().enumerate()
+        {
+            let first = hunk.start(Direction::Apply);
+            let (pre, _) = hunk.images(Direction::Apply);
+            if target.get(first..first + pre.len()) != Some(&pre[..]) {
+                return Err(Self::unplaced(
+                    &target,
+                    &pre,
+                    first,
+                    idx,
+                    |a, b| a == b,
+                ));
+            }
+            let last = first + pre.len();
+            match groups.last_mut() {
+                Some(group) if first <= group.last().unwrap().1 + 2 * lines => {
+                    group.push((first, last, hunk))
                 }
+                _ => groups.push(vec![(first, last, hunk)]),
             }
         }
 
-        while oidx < lines.len() {
-            buffer
-                .push_str(lines.get(oidx).expect("there is no line in lines"));
-            buffer.push('\n');
-            oidx += 1;
+        let context = |range: std::ops::Range<usize>| {
+            target[range].iter().map(|content| LineChange {
+                kind: Change::Default,
+                content: content.to_vec(),
+                no_newline: false,
+            })
+        };
+        for group in groups {
+            let start = group[0].0.saturating_sub(lines);
+            let end = (group.last().unwrap().1 + lines).min(target.len());
+            let new_start =
+                group[0].2.start(Direction::Revert) - (group[0].0 - start);
+            let mut change = context(start..group[0].0).collect::<Vec<_>>();
+            let mut heading = None;
+            let mut prev_last = None;
+            for (first, last, hunk) in group {
+                if let Some(prev_last) = prev_last {
+                    change.extend(context(prev_last..first));
+                }
+                heading = heading.or(hunk.heading);
+                change.extend(hunk.change);
+                prev_last = Some(last);
+            }
+            let last = prev_last.unwrap_or(start);
+            change.extend(context(last..end));
+            if no_newline && end == target.len() && last < end {
+                if let Some(change) = change.last_mut() {
+                    change.no_newline = true;
+                }
+            }
+            let count =
+                |skip: Change| change.iter().filter(|c| c.kind != skip).count();
+            let (old_len, new_len) =
+                (count(Change::Added), count(Change::Deleted));
+            // an empty range is numbered by the line before it
+            let line = |start: usize, len: usize| match len {
+                0 => start,
+                _ => start + 1,
+            };
+            self.hunk.push(DiffHunk {
+                old_line: line(start, old_len),
+                old_len,
+                new_line: line(new_start, new_len),
+                new_len,
+                heading,
+                change,
+            });
         }
+        Ok(())
+    }
 
-        Ok(buffer)
+    /// patch text, which stays UTF-8 when the lines of the diff are
+    fn patch_str(
+        &self,
+        target: &str,
+        direction: Direction,
+        options: &ApplyOptions,
+        unplaced: Unplaced,
+    ) -> Result<(String, ApplyReport), DiffError> {
+        let (after, report) =
+            self.patch(target.as_bytes(), direction, options, unplaced)?;
+        let after = String::from_utf8(after).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+        })?;
+        Ok((after, report))
     }
 
-    pub fn revert(&self, applied: &str) -> Result<String, DiffError> {
-        let mut buffer = String::new();
-
-        let mut aidx: usize = 0;
-        let lines: Vec<&str> = applied.lines().collect();
-
-        for hunk in &self.hunk {
-            while aidx < (hunk.new_line - 1) {
-                buffer.push_str(lines.get(aidx).ok_or_else(|| DiffError {
-                    kind: DiffErrorKind::InvalidIndex(aidx),
-                    reason: format!("cannot get line at {aidx}"),
-                })?);
-                buffer.push('\n');
-                aidx += 1;
-            }
-            for change in &hunk.change {
-                match change.kind {
-                    Change::Default => {
-                        let content =
-                            lines.get(aidx).ok_or_else(|| DiffError {
-                                kind: DiffErrorKind::InvalidIndex(aidx),
-                                reason: format!("cannot get line at {aidx}"),
-                            })?;
-                        if change.content != *content {
-                            Err(DiffError {
-                                kind: DiffErrorKind::UnmatchedContent(
-                                    change.content.to_string(),
-                                    content.to_string(),
-                                ),
-                                reason: format!(
-                                    "(change) : {:?}, line(content) : {}, @line_idx : {aidx}, Buffer:{}, hunk current {:#?}",
-                                    change, content, buffer, hunk
-                                ),
-                            })?;
-                        }
-                        buffer.push_str(content);
-                        buffer.push('\n');
-                        aidx += 1;
+    fn patch(
+        &self,
+        target: &[u8],
+        direction: Direction,
+        options: &ApplyOptions,
+        unplaced: Unplaced,
+    ) -> Result<(Vec<u8>, ApplyReport), DiffError> {
+        let mut report = ApplyReport {
+            path: self.path.clone(),
+            hunks: Vec::new(),
+            conflicts: Vec::new(),
+            rejected: Vec::new(),
+        };
+
+        // index of target line
+        let mut tidx: usize = 0;
+        // offset of the previous hunk, next hunk is likely to have same one
+        let mut last_offset: isize = 0;
+        // lines keep their carriage return, so line endings are preserved
+        let lines: Vec<&[u8]> = target
+            .split_inclusive(|b| *b == b'\n')
+            .map(|line| line.strip_suffix(b"\n").unwrap_or(line))
+            .collect();
+        // whether the last line of the target, then of the result, ends
+        // with a newline
+        let eol = target.is_empty() || target.ends_with(b"\n");
+        let mut end_eol = eol;
+        let mut out: Vec<Cow<[u8]>> = Vec::with_capacity(lines.len());
+        let ignore_cr = options.ignore_cr_at_eol;
+        let same = |a: &[u8], b: &[u8]| {
+            a == b
+                || ignore_cr
+                    && a.strip_suffix(b"\r").unwrap_or(a)
+                        == b.strip_suffix(b"\r").unwrap_or(b)
+        };
+        // line ending given to added lines, the one of most target lines
+        let cr = (ignore_cr && !lines.is_empty()).then(|| {
+            lines.iter().filter(|line| line.ends_with(b"\r")).count() * 2
+                > lines.len()
+        });
+
+        for (hidx, hunk) in self.hunk.iter().enumerate() {
+            let (pre, post) = hunk.images(direction);
+            let (pre_no_newline, post_no_newline) = hunk.no_newline(direction);
+            let (leading, trailing) = hunk.context();
+            let start = hunk.start(direction);
+
+            let mut placed = None;
+            for fuzz in 0..=options.fuzz {
+                let lead = fuzz.min(leading);
+                let trail = fuzz.min(trailing);
+                // a preimage without newline can only end the target
+                let eof = pre_no_newline && trail == 0;
+                if eof && eol {
+                    continue;
+                }
+                let expected =
+                    (start + lead).saturating_add_signed(last_offset);
+                if let Some(pos) = Self::find(
+                    &lines,
+                    &pre[lead..pre.len() - trail],
+                    expected,
+                    tidx,
+                    options.max_offset,
+                    eof,
+                    same,
+                ) {
+                    placed = Some((pos, lead, trail, fuzz));
+                    break;
+                }
+            }
+
+            let Some((pos, lead, trail, fuzz)) = placed else {
+                match unplaced {
+                    Unplaced::Fail => {
+                        Err(Self::unplaced(&lines, &pre, start, hidx, same))?
                     }
-                    Change::Deleted => {
-                        buffer.push_str(&change.content);
-                        buffer.push('\n');
+                    Unplaced::Reject => {
+                        report.rejected.push(HunkRejection {
+                            hunk: hidx,
+                            error: Self::unplaced(
+                                &lines, &pre, start, hidx, same,
+                            ),
+                        });
                     }
-                    Change::Added => {
-                        aidx += 1;
-                        continue;
+                    Unplaced::Conflict => {
+                        // take the lines at the expected position as ours
+                        let pos = start
+                            .saturating_add_signed(last_offset)
+                            .clamp(tidx, lines.len());
+                        let end = (pos + pre.len()).min(lines.len());
+                        out.extend(
+                            lines[tidx..pos].iter().copied().map(Cow::Borrowed),
+                        );
+                        if let Some(marker) = Self::merge_region(
+                            &mut out,
+                            &lines[pos..end],
+                            &pre,
+                            &post,
+                        ) {
+                            report.conflicts.push(MergeConflict {
+                                path: self.path.clone(),
+                                hunk: hidx,
+                                line: marker + 1,
+                            });
+                        }
+                        tidx = end;
                     }
                 }
+                continue;
+            };
+
+            let end = pos + pre.len() - lead - trail;
+            out.extend(lines[tidx..pos].iter().copied().map(Cow::Borrowed));
+            out.extend(hunk.postimage(
+                direction,
+                &lines[pos..end],
+                (lead, trail),
+                cr,
+            ));
+            tidx = end;
+            if trail == 0 && tidx == lines.len() {
+                end_eol = post.is_empty() || !post_no_newline;
             }
+
+            last_offset = pos as isize - (start + lead) as isize;
+            report.hunks.push(HunkPlacement {
+                hunk: hidx,
+                offset: last_offset,
+                fuzz,
+            });
         }
+        out.extend(lines[tidx..].iter().copied().map(Cow::Borrowed));
 
-        while aidx < lines.len() {
-            buffer
-                .push_str(lines.get(aidx).expect("there is no line in lines"));
-            buffer.push('\n');
-            aidx += 1;
+        let mut buffer = out.join(&b'\n');
+        if end_eol && !out.is_empty() {
+            buffer.push(b'\n');
         }
 
-        Ok(buffer)
+        Ok((buffer, report))
+    }
+
+    /// merge the postimage (`theirs`) of a hunk into the lines of the target
+    /// (`ours`), using the preimage (`base`) as merge base.
+    /// returns the index of the conflict marker in `out`, if written
+    fn merge_region<'a>(
+        out: &mut Vec<Cow<'a, [u8]>>,
+        ours: &[&'a [u8]],
+        base: &[&'a [u8]],
+        theirs: &[&'a [u8]],
+    ) -> Option<usize> {
+        let prefix = ours
+            .iter()
+            .zip(base.iter().zip(theirs))
+            .take_while(|(o, (b, t))| o == b && b == t)
+            .count();
+        let suffix = ours[prefix..]
+            .iter()
+            .rev()
+            .zip(
+                base[prefix..]
+                    .iter()
+                    .rev()
+                    .zip(theirs[prefix..].iter().rev()),
+            )
+            .take_while(|(o, (b, t))| o == b && b == t)
+            .count();
+        let mut region = Vec::with_capacity(ours.len() + theirs.len() + 3);
+        region.extend_from_slice(&ours[..prefix]);
+
+        let ours = &ours[prefix..ours.len() - suffix];
+        let base = &base[prefix..base.len() - suffix];
+        let (theirs, tail) = theirs.split_at(theirs.len() - suffix);
+        let theirs = &theirs[prefix..];
+
+        let conflicted = if ours == base || ours == theirs {
+            region.extend_from_slice(theirs);
+            None
+        } else if theirs == base {
+            region.extend_from_slice(ours);
+            None
+        } else {
+            let marker = out.len() + region.len();
+            region.push(DIFF_SIGN_CONFLICT_OURS.as_bytes());
+            region.extend_from_slice(ours);
+            region.push(DIFF_SIGN_CONFLICT_SEPARATOR.as_bytes());
+            region.extend_from_slice(theirs);
+            region.push(DIFF_SIGN_CONFLICT_THEIRS.as_bytes());
+            Some(marker)
+        };
+        region.extend_from_slice(tail);
+        out.extend(region.into_iter().map(Cow::Borrowed));
+        conflicted
+    }
+
+    /// search outward from `expected` for the position where `pre` matches,
+    /// not going before `min`. with `eof`, `pre` must end the target
+    fn find(
+        lines: &[&[u8]],
+        pre: &[&[u8]],
+        expected: usize,
+        min: usize,
+        max_offset: Option<usize>,
+        eof: bool,
+        same: impl Fn(&[u8], &[u8]) -> bool,
+    ) -> Option<usize> {
+        let last = lines.len().checked_sub(pre.len())?;
+        let matches = |pos: usize| {
+            lines[pos..pos + pre.len()]
+                .iter()
+                .zip(pre)
+                .all(|(line, expected)| same(line, expected))
+                && (!eof || pos == last)
+        };
+
+        let mut distance = 0;
+        loop {
+            if max_offset.is_some_and(|max| distance > max) {
+                return None;
+            }
+            let forward = expected + distance;
+            let backward =
+                expected.checked_sub(distance).filter(|pos| *pos >= min);
+            if forward > last && backward.is_none() {
+                return None;
+            }
+            if (min..=last).contains(&forward) && matches(forward) {
+                return Some(forward);
+            }
+            if let Some(pos) = backward.filter(|pos| *pos <= last) {
+                if distance > 0 && matches(pos) {
+                    return Some(pos);
+                }
+            }
+            distance += 1;
+        }
+    }
+
+    /// build the error for a hunk which cannot be placed, reporting the
+    /// first line differing from its recorded position
+    fn unplaced(
+        lines: &[&[u8]],
+        pre: &[&[u8]],
+        start: usize,
+        hunk_idx: usize,
+        same: impl Fn(&[u8], &[u8]) -> bool,
+    ) -> DiffError {
+        for (i, expected) in pre.iter().enumerate() {
+            let idx = start + i;
+            let Some(actual) = lines.get(idx) else {
+                return DiffError {
+                    kind: DiffErrorKind::InvalidIndex(idx),
+                    reason: format!("cannot get line at {idx}"),
+                };
+            };
+            if !same(actual, expected) {
+                let expected = String::from_utf8_lossy(expected);
+                let actual = String::from_utf8_lossy(actual);
+                return DiffError {
+                    kind: DiffErrorKind::UnmatchedContent {
+                        hunk: hunk_idx,
+                        line: idx + 1,
+                        expected: expected.to_string(),
+                        actual: actual.to_string(),
+                    },
+                    reason: format!(
+                        "unmatched content at hunk {hunk_idx}, line {}: expected {:?}, found {:?}",
+                        idx + 1,
+                        expected,
+                        actual
+                    ),
+                };
+            }
+        }
+        DiffError {
+            kind: DiffErrorKind::InvalidIndex(start),
+            reason: format!(
+                "hunk {hunk_idx} overlaps the previous hunk or does not match the newline at end of file"
+            ),
+        }
+    }
+}
+
+impl CombinedComposition {
+    /// diffs of the merge against one of its parents, 0 for the first.
+    /// files the merge takes unchanged from this parent are left out.
+    /// `--cc` also leaves out the hunks where the merge takes the lines of
+    /// one parent, the diff against the other ones then misses them
+    pub fn project(&self, parent: usize) -> Result<DiffComposition, DiffError> {
+        let mut diff = Vec::new();
+        for combined in &self.diff {
+            let projected = combined.project(parent)?;
+            let mode_change = projected.old_mode.is_some()
+                && projected.new_mode.is_some()
+                && projected.old_mode != projected.new_mode;
+            if !projected.hunk.is_empty()
+                || mode_change
+                || projected.status != FileStatus::Modified
+            {
+                diff.push(projected);
+            }
+        }
+        Ok(DiffComposition {
+            format: DiffFormat::GitUdiff,
+            diff,
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        })
+    }
+}
+
+impl CombinedDiff {
+    /// number of parents of the merge, given by the hunks or the index
+    pub fn parents(&self) -> usize {
+        match (self.hunk.first(), &self.index) {
+            (Some(hunk), _) => hunk.old_range.len(),
+            (None, Some(index)) => index.split(',').count(),
+            (None, None) => self.old_mode.len(),
+        }
+    }
+
+    /// diff of the merge against one of its parents, in git format
+    pub fn project(&self, parent: usize) -> Result<Diff, DiffError> {
+        let parents = self.parents();
+        if parent >= parents {
+            Err(DiffError {
+                kind: DiffErrorKind::InvalidIndex(parent),
+                reason: format!(
+                    "cannot project on parent {parent}, the merge has {parents}"
+                ),
+            })?;
+        }
+        let objects = self.index.as_deref().and_then(|index| {
+            let (old, new) = index.split_once("..")?;
+            Some((old.split(',').nth(parent)?, new))
+        });
+        let old_mode = self.old_mode.get(parent).copied();
+        let status = match objects {
+            // the file comes from the other parents only
+            Some((old, _))
+                if self.status == FileStatus::Modified
+                    && old.bytes().all(|b| b == b'0') =>
+            {
+                FileStatus::Added
+            }
+            _ => self.status,
+        };
+        let (mut old_mode, mut new_mode) = match status {
+            FileStatus::Added => (None, self.new_mode),
+            FileStatus::Deleted => (old_mode, None),
+            _ => (old_mode, self.new_mode),
+        };
+        let mut index = objects.map(|(old, new)| format!("{old}..{new}"));
+        // an unchanged mode is written on the index line
+        if let (Some(index), Some(mode)) = (&mut index, old_mode) {
+            if old_mode == new_mode {
+                index.push_str(&format!(" {mode:o}"));
+                (old_mode, new_mode) = (None, None);
+            }
+        }
+        let (old_name, name) =
+            (quote_path("a/", &self.path), quote_path("b/", &self.path));
+        Ok(Diff {
+            command: Some(format!("diff --git {old_name} {name}")),
+            index,
+            old_path: self.path.clone(),
+            path: self.path.clone(),
+            old_timestamp: None,
+            new_timestamp: None,
+            status,
+            old_mode,
+            new_mode,
+            similarity: None,
+            dissimilarity: None,
+            binary: None,
+            hunk: self
+                .hunk
+                .iter()
+                .map(|hunk| hunk.project(parent))
+                .filter(|hunk| {
+                    hunk.change.iter().any(|c| c.kind != Change::Default)
+                })
+                .collect(),
+        })
+    }
+}
+
+impl CombinedHunk {
+    /// lines of the parent and of the merge, with the lines the merge
+    /// removed from other parents left out
+    fn project(&self, parent: usize) -> DiffHunk {
+        let change = self
+            .change
+            .iter()
+            .filter_map(|line| {
+                let removed = line.kind.contains(&Change::Deleted);
+                let kind = match line.kind[parent] {
+                    Change::Default if removed => return None,
+                    kind => kind,
+                };
+                Some(LineChange {
+                    kind,
+                    content: line.content.clone(),
+                    no_newline: line.no_newline,
+                })
+            })
+            .collect();
+        let (old_line, old_len) = self.old_range[parent];
+        DiffHunk {
+            old_line,
+            old_len,
+            new_line: self.new_line,
+            new_len: self.new_len,
+            heading: self.heading.clone(),
+            change,
+        }
     }
 }
 
@@ -280,7 +1812,7 @@ impl Diff {
 mod test {
     use std::str::FromStr;
 
-    use {core::panic, std::fs};
+    use std::fs;
 
     use crate::{diff::*, parser::Parser};
 
@@ -336,6 +1868,179 @@ mod test {
         assert_eq!(applied.as_str(), expected.as_str())
     }
 
+    #[test]
+    fn test_diff_apply_unmatched() {
+        let original = fs::read_to_string("test_data/simple.before")
+            .unwrap()
+            .replace("(a - b) as i64", "(a - b) as i32");
+
+        let diff_file = fs::read_to_string("test_data/simple.diffs").unwrap();
+
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        let err = diff.apply(&original).unwrap_err();
+        println!("{:#?}", err);
+
+        match err.kind() {
+            DiffErrorKind::UnmatchedContent {
+                hunk,
+                line,
+                expected,
+                actual,
+            } => {
+                assert_eq!(*hunk, 0);
+                assert_eq!(*line, 12);
+                assert_eq!(expected, "    (a - b) as i64");
+                assert_eq!(actual, "    (a - b) as i32");
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_diff_apply_offset() {
+        let header = "// header\n// header\n// header\n";
+        let original = format!(
+            "{header}{}",
+            fs::read_to_string("test_data/simple.before").unwrap()
+        );
+
+        let diff_file = fs::read_to_string("test_data/simple.diffs").unwrap();
+
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        let (applied, report) = diff
+            .apply_with(&original, &ApplyOptions::default())
+            .unwrap();
+        println!("{:#?}", report);
+
+        let expected = format!(
+            "{header}{}",
+            fs::read_to_string("test_data/simple.after").unwrap()
+        );
+        assert_eq!(applied.as_str(), expected.as_str());
+        assert_eq!(
+            report.hunks,
+            vec![HunkPlacement {
+                hunk: 0,
+                offset: 3,
+                fuzz: 0
+            }]
+        );
+
+        let (reverted, report) = diff
+            .revert_with(&applied, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(reverted.as_str(), original.as_str());
+        assert_eq!(report.hunks[0].offset, 3);
+    }
+
+    #[test]
+    fn test_diff_apply_fuzz() {
+        let original = fs::read_to_string("test_data/simple.before")
+            .unwrap()
+            .replace("(a + b) as i64", "(a + b) as i128");
+
+        let diff_file = fs::read_to_string("test_data/simple.diffs").unwrap();
+
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        assert!(diff.apply(&original).is_err());
+
+        let options = ApplyOptions {
+            fuzz: 2,
+            max_offset: None,
+            ignore_cr_at_eol: false,
+        };
+        let (applied, report) = diff.apply_with(&original, &options).unwrap();
+        println!("{:#?}", report);
+
+        let expected = fs::read_to_string("test_data/simple.after")
+            .unwrap()
+            .replace("(a + b) as i64", "(a + b) as i128");
+        assert_eq!(applied.as_str(), expected.as_str());
+        assert_eq!(
+            report.hunks,
+            vec![HunkPlacement {
+                hunk: 0,
+                offset: 0,
+                fuzz: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_merge_conflict() {
+        let original = fs::read_to_string("test_data/simple.before")
+            .unwrap()
+            .replace("(a - b) as i64", "(a - b) as i32");
+
+        let diff_file = fs::read_to_string("test_data/simple.diffs").unwrap();
+
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        let (merged, conflicts) =
+            diff.merge(&original, &ApplyOptions::default()).unwrap();
+        println!("{}", merged);
+
+        assert_eq!(
+            conflicts,
+            vec![MergeConflict {
+                path: PathBuf::from("tmp.rs"),
+                hunk: 0,
+                line: 11
+            }]
+        );
+        let expected = r#"fn main() {
+    let foo = add(1, 2);
+    println!("Hello, world!");
+    println!("{foo}");
+}
+
+pub fn add(a: i32, b: i32) -> i64 {
+    (a + b) as i64
+}
+
+<<<<<<< ours
+pub fn sub(a: i32, b: i32) -> i64 {
+    (a - b) as i32
+}
+
+pub fn mul(a: i32, b: i32) -> i64 {
+    (a * b) as i64
+=======
+pub fn mul(a: i32, b: i32) -> i64 {
+    (a * b) as i64
+}
+
+pub fn mods(a: i32, b: i32) -> i64 {
+    (a % b) as i64
+>>>>>>> theirs
+}
+
+pub fn div(a: i32, b: i32) -> i64 {
+    (a / b) as i64
+}
+"#;
+        assert_eq!(merged.as_str(), expected);
+    }
+
+    #[test]
+    fn test_diff_merge_clean() {
+        let original = fs::read_to_string("test_data/simple.before").unwrap();
+
+        let diff_file = fs::read_to_string("test_data/simple.diffs").unwrap();
+
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        let (merged, conflicts) =
+            diff.merge(&original, &ApplyOptions::default()).unwrap();
+
+        let expected = fs::read_to_string("test_data/simple.after").unwrap();
+        assert!(conflicts.is_empty());
+        assert_eq!(merged.as_str(), expected.as_str());
+    }
+
     #[test]
     fn test_diff_apply_revert_simple() {
         let original = fs::read_to_string("test_data/simple.before").unwrap();
@@ -364,6 +2069,160 @@ mod test {
         assert_eq!(before.as_str(), original.as_str())
     }
 
+    #[test]
+    fn test_diff_apply_no_newline() {
+        let original = "fn main() {\n    println!(\"no newline\");\n}";
+        let expected = "fn main() {\n    println!(\"newline\");\n}\n";
+        let diff_file =
+            fs::read_to_string("test_data/no_newline.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        assert!(diff.hunk[0].change[2].no_newline);
+
+        let applied = diff.apply(original).unwrap();
+        assert_eq!(applied.as_str(), expected);
+        let before = diff.revert(&applied).unwrap();
+        assert_eq!(before.as_str(), original);
+
+        // the preimage ends the file without newline
+        assert!(diff.apply(&format!("{original}\n")).is_err());
+        assert!(diff.revert(&format!("{expected}// more\n")).is_ok());
+        assert!(diff.apply(&format!("{original}\n// more")).is_err());
+    }
+
+    #[test]
+    fn test_diff_apply_omitted_len() {
+        let src = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n";
+        let diff = &Parser::parse_git_udiff(src).unwrap().diff[0];
+        assert_eq!(diff.apply("a\n").unwrap(), "b\n");
+        assert_eq!(diff.revert("b\n").unwrap(), "a\n");
+        assert!(diff.apply("a\nb\n").is_ok());
+
+        // an empty file, the old range starts at line 0
+        let src = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -0,0 +1,3 @@\n+a\n+b\n+c\n";
+        let diff = &Parser::parse_git_udiff(src).unwrap().diff[0];
+        assert_eq!(diff.apply("").unwrap(), "a\nb\nc\n");
+        assert_eq!(diff.revert("a\nb\nc\n").unwrap(), "");
+
+        // content removed at the start of the file
+        let src =
+            "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1,2 +0,0 @@\n-a\n-b\n";
+        let diff = &Parser::parse_git_udiff(src).unwrap().diff[0];
+        assert_eq!(diff.apply("a\nb\n").unwrap(), "");
+        assert_eq!(diff.revert("").unwrap(), "a\nb\n");
+
+        // line 0 with a length is not valid, but does not underflow
+        let src = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -0 +0 @@\n-a\n+b\n";
+        let diff = &Parser::parse_git_udiff(src).unwrap().diff[0];
+        assert_eq!(diff.apply("a\n").unwrap(), "b\n");
+    }
+
+    #[test]
+    fn test_diff_apply_crlf() {
+        let original = fs::read_to_string("test_data/crlf.before").unwrap();
+        let expected = fs::read_to_string("test_data/crlf.after").unwrap();
+        let diff_file = fs::read_to_string("test_data/crlf.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        assert_eq!(diff.hunk[0].change[0].content, b"fn main() {\r");
+
+        // mixed line endings are kept as they are
+        let applied = diff.apply(&original).unwrap();
+        assert_eq!(applied, expected);
+        let before = diff.revert(&applied).unwrap();
+        assert_eq!(before, original);
+    }
+
+    #[test]
+    fn test_diff_add_context() {
+        let before = fs::read_to_string("test_data/middle.before").unwrap();
+        let after = fs::read_to_string("test_data/middle.after").unwrap();
+        let src = fs::read_to_string("test_data/normal/tree.diffs").unwrap();
+        let normal = || Parser::parse_normal(&src).unwrap().diff.remove(1);
+        // hunks without context still apply at their lines
+        assert_eq!(normal().apply(&before).unwrap(), after);
+
+        let git = fs::read_to_string("test_data/middle.diffs").unwrap();
+        let git = Parser::parse_git_udiff(&git).unwrap();
+        let lines = |diff: &Diff| {
+            diff.hunk
+                .iter()
+                .map(|h| {
+                    let change: Vec<_> =
+                        h.change.iter().map(|c| c.to_string()).collect();
+                    (h.old_line, h.old_len, h.new_line, h.new_len, change)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut diff = normal();
+        diff.add_context(before.as_bytes(), 3).unwrap();
+        assert_eq!(lines(&diff), lines(&git.diff[0]));
+        assert_eq!(diff.apply(&before).unwrap(), after);
+
+        // hunks whose context meets are merged
+        let mut diff = normal();
+        diff.add_context(before.as_bytes(), 5).unwrap();
+        assert_eq!(diff.hunk.len(), 2);
+        assert_eq!((diff.hunk[0].old_line, diff.hunk[0].old_len), (14, 20));
+        assert_eq!(diff.apply(&before).unwrap(), after);
+
+        let err = normal().add_context(after.as_bytes(), 3).unwrap_err();
+        assert!(matches!(err.kind(), DiffErrorKind::UnmatchedContent { .. }));
+    }
+
+    #[test]
+    fn test_diff_apply_bytes() {
+        // latin-1 content, which is not UTF-8
+        let original = fs::read("test_data/latin1.before").unwrap();
+        let expected = fs::read("test_data/latin1.after").unwrap();
+        let diff_file = fs::read("test_data/latin1.diffs").unwrap();
+        let com = Parser::parse_git_udiff_bytes(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        assert_eq!(
+            diff.hunk[0].change[2].content,
+            b"    println!(\"caf\xe9\");"
+        );
+
+        let options = ApplyOptions::default();
+        let (applied, _) = diff.apply_bytes(&original, &options).unwrap();
+        assert_eq!(applied, expected);
+        let (before, _) = diff.revert_bytes(&applied, &options).unwrap();
+        assert_eq!(before, original);
+
+        let comp_root = PathBuf::from_str("test_data/latin1").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        fs::write(comp_root.join("latin1.rs"), &original)
+            .expect("failed to write");
+        com.apply(&comp_root).unwrap();
+        assert_eq!(fs::read(comp_root.join("latin1.rs")).unwrap(), expected);
+        com.revert(&comp_root).unwrap();
+        assert_eq!(fs::read(comp_root.join("latin1.rs")).unwrap(), original);
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_diff_apply_ignore_cr_at_eol() {
+        let original = fs::read_to_string("test_data/crlf.before").unwrap();
+        let expected = fs::read_to_string("test_data/crlf.after").unwrap();
+        // the same diff, made on the file with unix line endings
+        let diff_file = fs::read_to_string("test_data/crlf.diffs")
+            .unwrap()
+            .replace('\r', "");
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let diff = com.diff.first().unwrap();
+        assert!(diff.apply(&original).is_err());
+
+        let options = ApplyOptions {
+            ignore_cr_at_eol: true,
+            ..Default::default()
+        };
+        // unchanged lines are kept, added lines take the most common ending
+        let (applied, _) = diff.apply_with(&original, &options).unwrap();
+        assert_eq!(applied, expected.replace("3;\n", "3;\r\n"));
+        let (before, _) = diff.revert_with(&applied, &options).unwrap();
+        assert_eq!(before, original.replace("2;\n", "2;\r\n"));
+    }
+
     #[test]
     fn test_diff_apply_revert_middle() {
         let original = fs::read_to_string("test_data/middle.before").unwrap();
@@ -422,7 +2281,7 @@ mod test {
         fs::copy("test_data/simple.after", "test_data/composition/simple_rev")
             .expect("failed to copy");
         let comp_root = PathBuf::from_str("test_data/composition").unwrap();
-        com.revert(&comp_root);
+        com.revert(&comp_root).unwrap();
         let reverted =
             fs::read_to_string("test_data/composition/simple_rev").unwrap();
         let expected = fs::read_to_string("test_data/simple.before").unwrap();
@@ -443,7 +2302,7 @@ mod test {
         )
         .expect("failed to copy");
         let comp_root = PathBuf::from_str("test_data/composition").unwrap();
-        com.apply(&comp_root);
+        com.apply(&comp_root).unwrap();
         let applied =
             fs::read_to_string("test_data/composition/middle_app").unwrap();
         let expected = fs::read_to_string("test_data/middle.after").unwrap();
@@ -461,7 +2320,7 @@ mod test {
         fs::copy("test_data/middle.after", "test_data/composition/middle_rev")
             .expect("failed to copy");
         let comp_root = PathBuf::from_str("test_data/composition").unwrap();
-        com.revert(&comp_root);
+        com.revert(&comp_root).unwrap();
         let reverted =
             fs::read_to_string("test_data/composition/middle_rev").unwrap();
         let expected = fs::read_to_string("test_data/middle.before").unwrap();
@@ -469,4 +2328,471 @@ mod test {
         fs::remove_file("test_data/composition/middle_rev")
             .expect("failed to remove file");
     }
+
+    #[test]
+    fn test_comp_middle_apply_partial() {
+        let diff_file =
+            fs::read_to_string("test_data/composition/middle_app.diffs")
+                .unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/partial").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        let original = fs::read_to_string("test_data/middle.before")
+            .unwrap()
+            .replace("eprintln!(\"Error {:?}\", err);", "todo!();");
+        fs::write(comp_root.join("middle_app"), original)
+            .expect("failed to write");
+
+        let reports = com
+            .apply_partial(&comp_root, &ApplyOptions::default())
+            .unwrap();
+        println!("{:#?}", reports);
+        let report = reports.first().unwrap();
+        assert_eq!(
+            report.hunks.iter().map(|h| h.hunk).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(
+            report.rejected.iter().map(|r| r.hunk).collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        let applied = fs::read_to_string(comp_root.join("middle_app")).unwrap();
+        let expected = fs::read_to_string("test_data/middle.after")
+            .unwrap()
+            .replace("panic!(\"VmError {:?}\", err)", "todo!();");
+        assert_eq!(applied.as_str(), expected.as_str());
+
+        let rejected =
+            fs::read_to_string(comp_root.join("middle_app.rej")).unwrap();
+        let expected = r#"--- a/middle_app
++++ b/middle_app
+@@ -25,7 +27,7 @@ fn run_vm_test(tests: Tests<Option<Object>>) {
+ 
+         while vm.is_runable() {
+             if let Err(err) = vm.run_single() {
+-                eprintln!("Error {:?}", err);
++                panic!("VmError {:?}", err)
+             }
+         }
+         println!("VM STACK:\n {}", vm.stack_to_string());
+"#;
+        assert_eq!(rejected.as_str(), expected);
+
+        // the file applies cleanly now, the old rejection is removed
+        fs::copy("test_data/middle.before", comp_root.join("middle_app"))
+            .expect("failed to copy");
+        let reports = com
+            .apply_partial(&comp_root, &ApplyOptions::default())
+            .unwrap();
+        assert!(reports[0].rejected.is_empty());
+        assert!(!comp_root.join("middle_app.rej").exists());
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_simple_check() {
+        let diff_file =
+            fs::read_to_string("test_data/composition/simple_app.diffs")
+                .unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/check").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+
+        let report = com.check(&comp_root);
+        println!("{:#?}", report);
+        assert!(!report.is_ok());
+        assert!(matches!(
+            report.files[0].rejected[0].error.kind(),
+            DiffErrorKind::IOError(_)
+        ));
+
+        fs::copy("test_data/simple.before", comp_root.join("simple_app"))
+            .expect("failed to copy");
+        let report = com.check(&comp_root);
+        println!("{:#?}", report);
+        assert!(report.is_ok());
+        assert_eq!(report.files[0].hunks.len(), 1);
+        assert!(!com.check_revert(&comp_root).is_ok());
+
+        let unchanged =
+            fs::read_to_string(comp_root.join("simple_app")).unwrap();
+        let expected = fs::read_to_string("test_data/simple.before").unwrap();
+        assert_eq!(unchanged.as_str(), expected.as_str());
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_same_file_apply_revert() {
+        let diff_file = "diff --git a/list b/list
+--- a/list
++++ b/list
+@@ -1,3 +1,3 @@
+ 1
+-2
++two
+ 3
+diff --git a/list b/list
+--- a/list
++++ b/list
+@@ -1,3 +1,3 @@
+ 1
+-two
++TWO
+ 3
+@@ -6,3 +6,3 @@
+ 6
+-7
++seven
+ 8
+";
+        let com = Parser::parse_git_udiff(diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/same_file").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        let original: String = (1..=9).map(|i| format!("{i}\n")).collect();
+        fs::write(comp_root.join("list"), &original).expect("failed to write");
+
+        // the second diff only fits the first one
+        assert!(com.check(&comp_root).is_ok());
+        let second = Parser::parse_git_udiff(
+            &diff_file[diff_file.rfind("diff --git").unwrap()..],
+        )
+        .unwrap();
+        assert!(!second.check(&comp_root).is_ok());
+
+        com.apply(&comp_root).unwrap();
+        assert!(com.check_revert(&comp_root).is_ok());
+        assert!(!com.check(&comp_root).is_ok());
+        let applied = fs::read_to_string(comp_root.join("list")).unwrap();
+        let expected = original.replace("2\n", "TWO\n").replace("7", "seven");
+        assert_eq!(applied, expected);
+        assert_eq!(fs::read_dir(&comp_root).unwrap().count(), 1);
+        com.revert(&comp_root).unwrap();
+        let reverted = fs::read_to_string(comp_root.join("list")).unwrap();
+        assert_eq!(reverted, original);
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_atomic_apply() {
+        let diff_file = format!(
+            "{}{}",
+            fs::read_to_string("test_data/composition/simple_app.diffs")
+                .unwrap(),
+            fs::read_to_string("test_data/composition/middle_app.diffs")
+                .unwrap()
+        );
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/atomic").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        fs::copy("test_data/simple.before", comp_root.join("simple_app"))
+            .expect("failed to copy");
+        let stale = fs::read_to_string("test_data/middle.before")
+            .unwrap()
+            .replace("eprintln!(\"Error {:?}\", err);", "todo!();");
+        fs::write(comp_root.join("middle_app"), &stale)
+            .expect("failed to write");
+
+        assert!(com.apply(&comp_root).is_err());
+        let simple = fs::read_to_string(comp_root.join("simple_app")).unwrap();
+        let expected = fs::read_to_string("test_data/simple.before").unwrap();
+        assert_eq!(simple.as_str(), expected.as_str());
+        let middle = fs::read_to_string(comp_root.join("middle_app")).unwrap();
+        assert_eq!(middle.as_str(), stale.as_str());
+        assert_eq!(fs::read_dir(&comp_root).unwrap().count(), 2);
+
+        fs::copy("test_data/middle.before", comp_root.join("middle_app"))
+            .expect("failed to copy");
+        com.apply(&comp_root).unwrap();
+        let simple = fs::read_to_string(comp_root.join("simple_app")).unwrap();
+        let expected = fs::read_to_string("test_data/simple.after").unwrap();
+        assert_eq!(simple.as_str(), expected.as_str());
+        let middle = fs::read_to_string(comp_root.join("middle_app")).unwrap();
+        let expected = fs::read_to_string("test_data/middle.after").unwrap();
+        assert_eq!(middle.as_str(), expected.as_str());
+        assert_eq!(fs::read_dir(&comp_root).unwrap().count(), 2);
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_files_apply_revert() {
+        let diff_file =
+            fs::read_to_string("test_data/composition/files.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/files").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        let removed = "fn old() {\n    println!(\"removed\");\n}\n";
+        let script = "#!/bin/sh\necho hello\n";
+        fs::write(comp_root.join("removed"), removed).expect("failed to write");
+        fs::write(comp_root.join("script"), script).expect("failed to write");
+        fs::write(comp_root.join("mode_only"), "keep\n")
+            .expect("failed to write");
+        #[cfg(unix)]
+        let mode = |name: &str| {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(comp_root.join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        #[cfg(unix)]
+        for name in ["script", "mode_only"] {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(
+                comp_root.join(name),
+                fs::Permissions::from_mode(0o644),
+            )
+            .expect("failed to set permissions");
+        }
+
+        com.apply(&comp_root).unwrap();
+        let added = fs::read_to_string(comp_root.join("added")).unwrap();
+        assert_eq!(added, "fn new() {\n    println!(\"added\");\n}\n");
+        assert_eq!(fs::read_to_string(comp_root.join("empty")).unwrap(), "");
+        assert!(!comp_root.join("removed").exists());
+        let patched = fs::read_to_string(comp_root.join("script")).unwrap();
+        assert_eq!(patched, "#!/bin/sh\necho hello world\n");
+        #[cfg(unix)]
+        {
+            assert_eq!(mode("script"), 0o755);
+            assert_eq!(mode("mode_only"), 0o755);
+            assert_eq!(mode("added"), mode("empty"));
+        }
+        assert!(matches!(
+            com.apply(&comp_root).unwrap_err().kind(),
+            DiffErrorKind::AlreadyExists(_)
+        ));
+
+        com.revert(&comp_root).unwrap();
+        assert!(!comp_root.join("added").exists());
+        assert!(!comp_root.join("empty").exists());
+        let restored = fs::read_to_string(comp_root.join("removed")).unwrap();
+        assert_eq!(restored.as_str(), removed);
+        let reverted = fs::read_to_string(comp_root.join("script")).unwrap();
+        assert_eq!(reverted.as_str(), script);
+        #[cfg(unix)]
+        {
+            assert_eq!(mode("script"), 0o644);
+            assert_eq!(mode("mode_only"), 0o644);
+            assert_eq!(mode("removed"), 0o644);
+        }
+        assert_eq!(fs::read_dir(&comp_root).unwrap().count(), 3);
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_rename_apply_revert() {
+        let diff_file =
+            fs::read_to_string("test_data/composition/rename.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/rename").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        let big: String = (1..=20).map(|n| format!("{n}\n")).collect();
+        fs::write(comp_root.join("big"), &big).expect("failed to write");
+        fs::write(comp_root.join("mode_only"), "keep\n")
+            .expect("failed to write");
+
+        com.apply(&comp_root).unwrap();
+        assert!(!comp_root.join("big").exists());
+        assert!(!comp_root.join("mode_only").exists());
+        let copied = fs::read_to_string(comp_root.join("copy_dst")).unwrap();
+        assert_eq!(copied, format!("{big}21\n"));
+        let moved = fs::read_to_string(comp_root.join("moved")).unwrap();
+        assert_eq!(moved, big.replace("\n5\n", "\nfive\n"));
+        let renamed =
+            fs::read_to_string(comp_root.join("renamed_plain")).unwrap();
+        assert_eq!(renamed, "keep\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(comp_root.join("moved")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+
+        com.revert(&comp_root).unwrap();
+        let reverted = fs::read_to_string(comp_root.join("big")).unwrap();
+        assert_eq!(reverted, big);
+        let restored = fs::read_to_string(comp_root.join("mode_only")).unwrap();
+        assert_eq!(restored, "keep\n");
+        assert_eq!(fs::read_dir(&comp_root).unwrap().count(), 2);
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_unified_apply_revert() {
+        let diff_file =
+            fs::read_to_string("test_data/unified/single.diffs").unwrap();
+        let com = Parser::parse_unified(&diff_file).unwrap();
+        let comp_root = PathBuf::from_str("test_data/unified/single").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        let main = "fn main() {\n    let a = 1;\n    println!(\"{a}\");\n}\n";
+        fs::write(comp_root.join("main.rs"), main).expect("failed to write");
+        fs::write(comp_root.join("stale"), "old\n").expect("failed to write");
+
+        // patched in place, under the name of the new file
+        com.apply(&comp_root).unwrap();
+        let patched = fs::read_to_string(comp_root.join("main.rs")).unwrap();
+        assert_eq!(patched, main.replace("1;", "2;"));
+        assert!(!comp_root.join("main.rs.orig").exists());
+        let added = fs::read_to_string(comp_root.join("added")).unwrap();
+        assert_eq!(added, "new\n");
+        assert!(!comp_root.join("stale").exists());
+
+        com.revert(&comp_root).unwrap();
+        let reverted = fs::read_to_string(comp_root.join("main.rs")).unwrap();
+        assert_eq!(reverted, main);
+        assert!(!comp_root.join("added").exists());
+        assert!(comp_root.join("stale").exists());
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_binary_apply_revert() {
+        let diff_file =
+            fs::read_to_string("test_data/binary/binary.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root = PathBuf::from_str("test_data/binary/apply").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        fs::copy("test_data/binary/blob.before", comp_root.join("blob.bin"))
+            .expect("failed to copy");
+        fs::copy("test_data/binary/gone.bin", comp_root.join("gone.bin"))
+            .expect("failed to copy");
+
+        assert!(com.check(&comp_root).is_ok());
+        com.apply(&comp_root).unwrap();
+        let blob = fs::read(comp_root.join("blob.bin")).unwrap();
+        assert_eq!(blob, fs::read("test_data/binary/blob.after").unwrap());
+        let new = fs::read(comp_root.join("new.bin")).unwrap();
+        assert_eq!(new, fs::read("test_data/binary/new.bin").unwrap());
+        assert!(!comp_root.join("gone.bin").exists());
+        // the delta does not fit the patched file anymore
+        let report = com.check(&comp_root);
+        assert!(matches!(
+            report.files[0].rejected[0].error.kind(),
+            DiffErrorKind::InvalidDelta
+        ));
+
+        com.revert(&comp_root).unwrap();
+        let blob = fs::read(comp_root.join("blob.bin")).unwrap();
+        assert_eq!(blob, fs::read("test_data/binary/blob.before").unwrap());
+        let gone = fs::read(comp_root.join("gone.bin")).unwrap();
+        assert_eq!(gone, fs::read("test_data/binary/gone.bin").unwrap());
+        assert!(!comp_root.join("new.bin").exists());
+
+        let diff_file =
+            fs::read_to_string("test_data/binary/stub.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let report = com.check(&comp_root);
+        assert!(matches!(
+            report.files[0].rejected[0].error.kind(),
+            DiffErrorKind::NotApplicable(_)
+        ));
+        assert!(matches!(
+            com.apply(&comp_root).unwrap_err().kind(),
+            DiffErrorKind::NotApplicable(_)
+        ));
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_comp_binary_apply_partial() {
+        let diff_file =
+            fs::read_to_string("test_data/binary/stub.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&diff_file).unwrap();
+        let comp_root =
+            PathBuf::from_str("test_data/composition/binary_partial").unwrap();
+        fs::create_dir_all(&comp_root).expect("failed to create dir");
+        fs::copy("test_data/binary/blob.before", comp_root.join("blob.bin"))
+            .expect("failed to copy");
+        fs::copy("test_data/binary/gone.bin", comp_root.join("gone.bin"))
+            .expect("failed to copy");
+
+        // a stub cannot be applied, each diff is rejected as a whole
+        let reports = com
+            .apply_partial(&comp_root, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(reports.len(), 3);
+        for report in &reports {
+            assert!(report.hunks.is_empty());
+            assert_eq!(
+                report.rejected.iter().map(|r| r.hunk).collect::<Vec<_>>(),
+                vec![0]
+            );
+        }
+        let blob = fs::read(comp_root.join("blob.bin")).unwrap();
+        assert_eq!(blob, fs::read("test_data/binary/blob.before").unwrap());
+        let starts: Vec<_> = diff_file
+            .match_indices("diff --git")
+            .map(|(at, _)| at)
+            .collect();
+        for (i, name) in ["blob.bin", "gone.bin", "new.bin"].iter().enumerate()
+        {
+            let end = starts.get(i + 1).copied().unwrap_or(diff_file.len());
+            let rejected =
+                fs::read_to_string(comp_root.join(format!("{name}.rej")))
+                    .unwrap();
+            assert_eq!(rejected.as_str(), &diff_file[starts[i]..end]);
+        }
+        fs::remove_dir_all(&comp_root).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn test_combined_project() {
+        let src =
+            fs::read_to_string("test_data/combined/combined.diffs").unwrap();
+        let com = Parser::parse_combined(&src).unwrap();
+        for (parent, dir) in ["ours", "theirs"].iter().enumerate() {
+            let projected = com.project(parent).unwrap();
+            assert_eq!(projected.diff.len(), 5);
+            for diff in &projected.diff[1..3] {
+                let name = diff.path.to_str().unwrap();
+                let read = |dir: &str| {
+                    fs::read_to_string(format!(
+                        "test_data/combined/{dir}/{name}"
+                    ))
+                    .unwrap()
+                };
+                assert_eq!(diff.apply(&read(dir)).unwrap(), read("merged"));
+            }
+            assert_eq!(projected.diff[0].status, FileStatus::Added);
+            assert_eq!(projected.diff[3].status, FileStatus::Deleted);
+            assert_eq!(projected.diff[3].old_mode, Some(0o100644));
+            assert_eq!(projected.diff[3].hunk[0].change.len(), 2);
+        }
+        // the mode of the second parent is kept
+        let run = com.diff[4].project(0).unwrap();
+        assert_eq!(
+            (run.old_mode, run.new_mode),
+            (Some(0o100644), Some(0o100755))
+        );
+        let run = com.diff[4].project(1).unwrap();
+        assert_eq!(run.index.as_deref(), Some("028c7b6..3e58b90 100755"));
+        assert_eq!((run.old_mode, run.new_mode), (None, None));
+        assert!(matches!(
+            com.project(2).unwrap_err().kind(),
+            DiffErrorKind::InvalidIndex(2)
+        ));
+
+        // `--cc` leaves out the hunks where the merge takes one side
+        let src = fs::read_to_string("test_data/combined/cc.diffs").unwrap();
+        let com = Parser::parse_combined(&src).unwrap();
+        for parent in 0..2 {
+            let projected = com.project(parent).unwrap();
+            assert_eq!(projected.diff[1].hunk.len(), 1);
+            assert_eq!(projected.diff[1].hunk[0].change.len(), 7);
+        }
+        let read = |dir: &str| {
+            fs::read_to_string(format!("test_data/combined/{dir}/list.txt"))
+                .unwrap()
+        };
+        let projected = com.project(0).unwrap();
+        assert_eq!(
+            projected.diff[1].apply(&read("ours")).unwrap(),
+            read("merged").replace("fifteen", "15")
+        );
+    }
 }
@@ -0,0 +1,1437 @@
+use {
+    crate::{diff::*, writer::quote_path},
+    regex::Regex,
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// lines of the old and new text, as ids of equal lines,
+/// with the changed lines marked
+struct Classified {
+    old: Vec<usize>,
+    new: Vec<usize>,
+    old_changed: Vec<bool>,
+    new_changed: Vec<bool>,
+}
+
+impl Classified {
+    fn new(old: &[&str], new: &[&str]) -> Self {
+        fn classify<'a>(
+            ids: &mut HashMap<&'a str, usize>,
+            lines: &[&'a str],
+        ) -> Vec<usize> {
+            lines
+                .iter()
+                .map(|line| {
+                    let next = ids.len();
+                    *ids.entry(line).or_insert(next)
+                })
+                .collect()
+        }
+        let mut ids = HashMap::new();
+        let old_ids = classify(&mut ids, old);
+        let new_ids = classify(&mut ids, new);
+        Classified {
+            old_changed: vec![false; old_ids.len()],
+            new_changed: vec![false; new_ids.len()],
+            old: old_ids,
+            new: new_ids,
+        }
+    }
+}
+
+/// options used to generate a diff
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// number of unchanged lines kept around each change
+    pub context: usize,
+    pub algorithm: DiffAlgorithm,
+    /// rules to find the section heading of the hunks,
+    /// `None` to leave the headings empty
+    pub funcname: Option<FuncName>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            context: 3,
+            algorithm: DiffAlgorithm::Myers,
+            funcname: Some(FuncName::default()),
+        }
+    }
+}
+
+/// max length in bytes of a section heading, as in git
+const FUNCNAME_MAX_LEN: usize = 80;
+
+/// rules to find the section heading of a hunk, like git's `diff.*.xfuncname`.
+///
+/// the heading of a hunk is the nearest line above it which is a "function
+/// name". by default, that is a line starting with a letter, `_` or `$`.
+/// a pattern can be set per file extension instead
+#[derive(Debug, Clone, Default)]
+pub struct FuncName {
+    /// regexes per extension, with whether they are negated
+    patterns: HashMap<String, Vec<(Regex, bool)>>,
+}
+
+impl FuncName {
+    /// set the pattern used for files with `extension`.
+    ///
+    /// like git, the pattern holds one regex per line. a line is a function
+    /// name if the first regex matching it is not prefixed with `!`.
+    /// the heading is the first capture group of the match, if any
+    pub fn pattern(
+        mut self,
+        extension: &str,
+        pattern: &str,
+    ) -> Result<Self, regex::Error> {
+        let regexes = pattern
+            .lines()
+            .map(|line| match line.strip_prefix('!') {
+                Some(negated) => Ok((Regex::new(negated)?, true)),
+                None => Ok((Regex::new(line)?, false)),
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        self.patterns.insert(extension.to_string(), regexes);
+        Ok(self)
+    }
+
+    /// the heading given by `line` of the file at `path`, if it is a
+    /// function name
+    pub fn heading(&self, path: &Path, line: &str) -> Option<String> {
+        let patterns = path
+            .extension()
+            .and_then(|ext| self.patterns.get(ext.to_str()?));
+        let heading = match patterns {
+            Some(regexes) => {
+                let (captures, negated) = regexes
+                    .iter()
+                    .find_map(|(re, neg)| Some((re.captures(line)?, *neg)))?;
+                if negated {
+                    return None;
+                }
+                captures.get(1).or_else(|| captures.get(0))?.as_str()
+            }
+            None => {
+                let first = line.chars().next()?;
+                if !(first.is_ascii_alphabetic()
+                    || first == '_'
+                    || first == '$')
+                {
+                    return None;
+                }
+                line
+            }
+        };
+        let mut end = heading.len().min(FUNCNAME_MAX_LEN);
+        while !heading.is_char_boundary(end) {
+            end -= 1;
+        }
+        Some(heading[..end].trim_end().to_string())
+    }
+}
+
+// limits used by xdiff, which the algorithms below follow to give the same
+// output as git
+const MAX_EQLIMIT: usize = 1024;
+const SIMSCAN_WINDOW: usize = 100;
+const KPDIS_RUN: usize = 4;
+const HISTOGRAM_MAX_CHAIN: usize = 64;
+const MAX_INDENT: isize = 200;
+const MAX_BLANKS: isize = 20;
+const INDENT_HEURISTIC_MAX_SLIDING: usize = 100;
+// cost cutoffs of the middle snake search, unless the diff has to be minimal
+const MAX_COST_MIN: usize = 256;
+const HEUR_MIN_COST: usize = 256;
+const SNAKE_CNT: isize = 20;
+const K_HEUR: isize = 4;
+
+/// xdiff's cheap approximation of a square root
+fn bogosqrt(mut n: usize) -> usize {
+    let mut i = 1;
+    while n > 0 {
+        i <<= 1;
+        n >>= 2;
+    }
+    i
+}
+
+/// Myers' diff of `old[o1..o2]` and `new[n1..n2]`.
+/// like xdiff, the common ends are trimmed and the lines without match on
+/// the other side are marked before searching for middle snakes. unless
+/// `minimal`, the search of costly snakes is cut short as git does
+fn myers(
+    cls: &mut Classified,
+    (o1, o2): (usize, usize),
+    (n1, n2): (usize, usize),
+    minimal: bool,
+) {
+    let (old, new) = (&cls.old[o1..o2], &cls.new[n1..n2]);
+
+    // occurrences of each line in old and in new
+    let mut count: HashMap<usize, (usize, usize)> = HashMap::new();
+    for id in old {
+        count.entry(*id).or_default().0 += 1;
+    }
+    for id in new {
+        count.entry(*id).or_default().1 += 1;
+    }
+
+    let shorter = old.len().min(new.len());
+    let prefix = (0..shorter).take_while(|&i| old[i] == new[i]).count();
+    let suffix = (0..shorter - prefix)
+        .take_while(|&i| old[old.len() - 1 - i] == new[new.len() - 1 - i])
+        .count();
+
+    // lines kept for the search, with their index
+    let reduce = |lines: &[usize], other: fn(&(usize, usize)) -> usize| {
+        let (start, end) = (prefix, lines.len() - suffix);
+        let limit = bogosqrt(lines.len()).min(MAX_EQLIMIT);
+        // 0: no match, 1: matches, 2: matches too many lines
+        let mut discard = vec![0_u8; lines.len()];
+        for i in start..end {
+            discard[i] = match other(&count[&lines[i]]) {
+                0 => 0,
+                matches if matches >= limit => 2,
+                _ => 1,
+            };
+        }
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        for i in start..end {
+            if discard[i] == 1
+                || (discard[i] == 2
+                    && !clean_mmatch(&discard, i, start, end - 1))
+            {
+                kept.push(i);
+            } else {
+                dropped.push(i);
+            }
+        }
+        (kept, dropped)
+    };
+    let (kept1, dropped1) = reduce(old, |c| c.1);
+    let (kept2, dropped2) = reduce(new, |c| c.0);
+
+    for i in dropped1 {
+        cls.old_changed[o1 + i] = true;
+    }
+    for i in dropped2 {
+        cls.new_changed[n1 + i] = true;
+    }
+
+    let ha1: Vec<usize> = kept1.iter().map(|&i| cls.old[o1 + i]).collect();
+    let ha2: Vec<usize> = kept2.iter().map(|&i| cls.new[n1 + i]).collect();
+    let mut changed1 = vec![false; ha1.len()];
+    let mut changed2 = vec![false; ha2.len()];
+    let max_cost = bogosqrt(ha1.len() + ha2.len() + 3).max(MAX_COST_MIN);
+    recs_cmp(
+        (&ha1, &mut changed1),
+        (&ha2, &mut changed2),
+        (0, ha1.len()),
+        (0, ha2.len()),
+        (minimal, max_cost as isize),
+    );
+    for (i, _) in kept1.iter().zip(changed1).filter(|(_, c)| *c) {
+        cls.old_changed[o1 + i] = true;
+    }
+    for (i, _) in kept2.iter().zip(changed2).filter(|(_, c)| *c) {
+        cls.new_changed[n1 + i] = true;
+    }
+}
+
+/// whether a line matching too many lines should be discarded, which
+/// is when it sits in a run of lines without match
+fn clean_mmatch(discard: &[u8], i: usize, start: usize, end: usize) -> bool {
+    let start = start.max(i.saturating_sub(SIMSCAN_WINDOW));
+    let end = end.min(i + SIMSCAN_WINDOW);
+
+    let (mut before_none, mut before_many) = (0, 1);
+    for j in (start..i).rev() {
+        match discard[j] {
+            0 => before_none += 1,
+            2 => before_many += 1,
+            _ => break,
+        }
+    }
+    if before_none == 0 {
+        return false;
+    }
+    let (mut after_none, mut after_many) = (0, 1);
+    for &d in &discard[i + 1..=end] {
+        match d {
+            0 => after_none += 1,
+            2 => after_many += 1,
+            _ => break,
+        }
+    }
+    if after_none == 0 {
+        return false;
+    }
+    let none = before_none + after_none;
+    let many = before_many + after_many;
+    many * KPDIS_RUN < many + none
+}
+
+/// divide and conquer on the middle snakes of `ha1[off1..lim1]` and
+/// `ha2[off2..lim2]`, marking the changed lines. the halves of a split
+/// found by a heuristic are searched minimally on their split side
+fn recs_cmp(
+    (ha1, changed1): (&[usize], &mut [bool]),
+    (ha2, changed2): (&[usize], &mut [bool]),
+    (mut off1, mut lim1): (usize, usize),
+    (mut off2, mut lim2): (usize, usize),
+    (minimal, max_cost): (bool, isize),
+) {
+    while off1 < lim1 && off2 < lim2 && ha1[off1] == ha2[off2] {
+        off1 += 1;
+        off2 += 1;
+    }
+    while off1 < lim1 && off2 < lim2 && ha1[lim1 - 1] == ha2[lim2 - 1] {
+        lim1 -= 1;
+        lim2 -= 1;
+    }
+
+    if off1 == lim1 {
+        changed2[off2..lim2].fill(true);
+    } else if off2 == lim2 {
+        changed1[off1..lim1].fill(true);
+    } else {
+        let ((i1, i2), (min_lo, min_hi)) =
+            split(ha1, (off1, lim1), ha2, (off2, lim2), (minimal, max_cost));
+        recs_cmp(
+            (ha1, &mut *changed1),
+            (ha2, &mut *changed2),
+            (off1, i1),
+            (off2, i2),
+            (min_lo, max_cost),
+        );
+        recs_cmp(
+            (ha1, changed1),
+            (ha2, changed2),
+            (i1, lim1),
+            (i2, lim2),
+            (min_hi, max_cost),
+        );
+    }
+}
+
+/// find the middle snake of the shortest edit script, extending the
+/// forward and backward paths together. returns the point to split at,
+/// with whether each half still needs a minimal diff.
+///
+/// unless `minimal`, like xdiff, a costly search stops early: past
+/// `HEUR_MIN_COST`, at a diagonal reaching far on a long enough snake, and
+/// past `max_cost`, at the furthest reaching diagonal
+fn split(
+    ha1: &[usize],
+    (off1, lim1): (usize, usize),
+    ha2: &[usize],
+    (off2, lim2): (usize, usize),
+    (minimal, max_cost): (bool, isize),
+) -> ((usize, usize), (bool, bool)) {
+    let (off1, lim1) = (off1 as isize, lim1 as isize);
+    let (off2, lim2) = (off2 as isize, lim2 as isize);
+    let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+    let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+    let odd = (fmid - bmid) & 1 != 0;
+    let (mut fmin, mut fmax) = (fmid, fmid);
+    let (mut bmin, mut bmax) = (bmid, bmid);
+    let found = |i1: isize, i2: isize, min_lo: bool, min_hi: bool| {
+        ((i1 as usize, i2 as usize), (min_lo, min_hi))
+    };
+
+    // furthest reaching line of `ha1` on each diagonal
+    let at = |d: isize| (d - dmin + 1) as usize;
+    let size = (dmax - dmin + 3) as usize;
+    let mut kvdf = vec![-1_isize; size];
+    let mut kvdb = vec![isize::MAX; size];
+    kvdf[at(fmid)] = off1;
+    kvdb[at(bmid)] = lim1;
+
+    let mut cost = 0;
+    loop {
+        cost += 1;
+        let mut got_snake = false;
+
+        if fmin > dmin {
+            fmin -= 1;
+            kvdf[at(fmin - 1)] = -1;
+        } else {
+            fmin += 1;
+        }
+        if fmax < dmax {
+            fmax += 1;
+            kvdf[at(fmax + 1)] = -1;
+        } else {
+            fmax -= 1;
+        }
+
+        let mut d = fmax;
+        while d >= fmin {
+            let mut i1 = if kvdf[at(d - 1)] >= kvdf[at(d + 1)] {
+                kvdf[at(d - 1)] + 1
+            } else {
+                kvdf[at(d + 1)]
+            };
+            let prev1 = i1;
+            let mut i2 = i1 - d;
+            while i1 < lim1 && i2 < lim2 && ha1[i1 as usize] == ha2[i2 as usize]
+            {
+                i1 += 1;
+                i2 += 1;
+            }
+            got_snake |= i1 - prev1 > SNAKE_CNT;
+            kvdf[at(d)] = i1;
+            if odd && bmin <= d && d <= bmax && kvdb[at(d)] <= i1 {
+                return found(i1, i2, true, true);
+            }
+            d -= 2;
+        }
+
+        if bmin > dmin {
+            bmin -= 1;
+            kvdb[at(bmin - 1)] = isize::MAX;
+        } else {
+            bmin += 1;
+        }
+        if bmax < dmax {
+            bmax += 1;
+            kvdb[at(bmax + 1)] = isize::MAX;
+        } else {
+            bmax -= 1;
+        }
+
+        let mut d = bmax;
+        while d >= bmin {
+            let mut i1 = if kvdb[at(d - 1)] < kvdb[at(d + 1)] {
+                kvdb[at(d - 1)]
+            } else {
+                kvdb[at(d + 1)] - 1
+            };
+            let prev1 = i1;
+            let mut i2 = i1 - d;
+            while i1 > off1
+                && i2 > off2
+                && ha1[i1 as usize - 1] == ha2[i2 as usize - 1]
+            {
+                i1 -= 1;
+                i2 -= 1;
+            }
+            got_snake |= prev1 - i1 > SNAKE_CNT;
+            kvdb[at(d)] = i1;
+            if !odd && fmin <= d && d <= fmax && i1 <= kvdf[at(d)] {
+                return found(i1, i2, true, true);
+            }
+            d -= 2;
+        }
+
+        if minimal {
+            continue;
+        }
+
+        // a diagonal gone far from the corner, for its distance to the
+        // middle diagonal, and ending a snake is taken as a good split
+        if got_snake && cost > HEUR_MIN_COST as isize {
+            let mut best = None;
+            let mut best_value = 0;
+            for d in (fmin..=fmax).rev().step_by(2) {
+                let i1 = kvdf[at(d)];
+                let i2 = i1 - d;
+                let value = (i1 - off1) + (i2 - off2) - (d - fmid).abs();
+                if value > K_HEUR * cost
+                    && value > best_value
+                    && off1 + SNAKE_CNT <= i1
+                    && i1 < lim1
+                    && off2 + SNAKE_CNT <= i2
+                    && i2 < lim2
+                    && (1..=SNAKE_CNT).all(|k| {
+                        ha1[(i1 - k) as usize] == ha2[(i2 - k) as usize]
+                    })
+                {
+                    best_value = value;
+                    best = Some((i1, i2));
+                }
+            }
+            if let Some((i1, i2)) = best {
+                return found(i1, i2, true, false);
+            }
+
+            let mut best_value = 0;
+            for d in (bmin..=bmax).rev().step_by(2) {
+                let i1 = kvdb[at(d)];
+                let i2 = i1 - d;
+                let value = (lim1 - i1) + (lim2 - i2) - (d - bmid).abs();
+                if value > K_HEUR * cost
+                    && value > best_value
+                    && off1 < i1
+                    && i1 <= lim1 - SNAKE_CNT
+                    && off2 < i2
+                    && i2 <= lim2 - SNAKE_CNT
+                    && (0..SNAKE_CNT).all(|k| {
+                        ha1[(i1 + k) as usize] == ha2[(i2 + k) as usize]
+                    })
+                {
+                    best_value = value;
+                    best = Some((i1, i2));
+                }
+            }
+            if let Some((i1, i2)) = best {
+                return found(i1, i2, false, true);
+            }
+        }
+
+        // too costly, split at the path reaching the furthest
+        if cost >= max_cost {
+            let (mut fbest, mut fbest1) = (-1, -1);
+            for d in (fmin..=fmax).rev().step_by(2) {
+                let mut i1 = kvdf[at(d)].min(lim1);
+                let mut i2 = i1 - d;
+                if lim2 < i2 {
+                    (i1, i2) = (lim2 + d, lim2);
+                }
+                if fbest < i1 + i2 {
+                    (fbest, fbest1) = (i1 + i2, i1);
+                }
+            }
+            let (mut bbest, mut bbest1) = (isize::MAX, isize::MAX);
+            for d in (bmin..=bmax).rev().step_by(2) {
+                let mut i1 = kvdb[at(d)].max(off1);
+                let mut i2 = i1 - d;
+                if i2 < off2 {
+                    (i1, i2) = (off2 + d, off2);
+                }
+                if i1 + i2 < bbest {
+                    (bbest, bbest1) = (i1 + i2, i1);
+                }
+            }
+            return if (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+                found(fbest1, fbest - fbest1, true, false)
+            } else {
+                found(bbest1, bbest - bbest1, false, true)
+            };
+        }
+    }
+}
+
+/// patience diff of `old[o1..o2]` and `new[n1..n2]`: the longest common
+/// sequence of the lines unique on both sides anchors the diff, and the
+/// gaps between them are diffed again
+fn patience(cls: &mut Classified, o1: usize, o2: usize, n1: usize, n2: usize) {
+    if o1 == o2 || n1 == n2 {
+        cls.old_changed[o1..o2].fill(true);
+        cls.new_changed[n1..n2].fill(true);
+        return;
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Unique {
+        Missing,
+        At(usize),
+        Many,
+    }
+    // lines of old in order of first occurrence, with their line in new
+    let mut entries: Vec<(usize, Unique)> = Vec::new();
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    for line in o1..o2 {
+        match index.get(&cls.old[line]) {
+            Some(&e) => entries[e].1 = Unique::Many,
+            None => {
+                index.insert(cls.old[line], entries.len());
+                entries.push((line, Unique::Missing));
+            }
+        }
+    }
+    let mut has_matches = false;
+    for line in n1..n2 {
+        if let Some(&e) = index.get(&cls.new[line]) {
+            has_matches = true;
+            entries[e].1 = match entries[e].1 {
+                Unique::Missing => Unique::At(line),
+                _ => Unique::Many,
+            };
+        }
+    }
+    if !has_matches {
+        cls.old_changed[o1..o2].fill(true);
+        cls.new_changed[n1..n2].fill(true);
+        return;
+    }
+
+    // patience sorting of the unique lines by their line in new
+    let unique: Vec<(usize, usize)> = entries
+        .iter()
+        .filter_map(|&(line1, line2)| match line2 {
+            Unique::At(line2) => Some((line1, line2)),
+            _ => None,
+        })
+        .collect();
+    if unique.is_empty() {
+        myers(cls, (o1, o2), (n1, n2), false);
+        return;
+    }
+    let mut piles: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; unique.len()];
+    for (idx, &(_, line2)) in unique.iter().enumerate() {
+        let pile = piles.partition_point(|&top| unique[top].1 <= line2);
+        previous[idx] = pile.checked_sub(1).map(|p| piles[p]);
+        if pile == piles.len() {
+            piles.push(idx);
+        } else {
+            piles[pile] = idx;
+        }
+    }
+    let mut common = Vec::new();
+    let mut cur = piles.last().copied();
+    while let Some(idx) = cur {
+        common.push(unique[idx]);
+        cur = previous[idx];
+    }
+    common.reverse();
+
+    let (mut line1, mut line2) = (o1, n1);
+    let mut idx = 0;
+    loop {
+        // grow the common lines backward and forward
+        let (mut next1, mut next2) = match common.get(idx) {
+            Some(&(next1, next2)) => (next1, next2),
+            None => (o2, n2),
+        };
+        if idx < common.len() {
+            while next1 > line1
+                && next2 > line2
+                && cls.old[next1 - 1] == cls.new[next2 - 1]
+            {
+                next1 -= 1;
+                next2 -= 1;
+            }
+        }
+        while line1 < next1 && line2 < next2 && cls.old[line1] == cls.new[line2]
+        {
+            line1 += 1;
+            line2 += 1;
+        }
+        if next1 > line1 || next2 > line2 {
+            patience(cls, line1, next1, line2, next2);
+        }
+        if idx >= common.len() {
+            return;
+        }
+        while idx + 1 < common.len()
+            && common[idx + 1] == (common[idx].0 + 1, common[idx].1 + 1)
+        {
+            idx += 1;
+        }
+        (line1, line2) = (common[idx].0 + 1, common[idx].1 + 1);
+        idx += 1;
+    }
+}
+
+/// histogram diff of `old[o1..o2]` and `new[n1..n2]`: the longest common
+/// region made of the lines occurring the least in old splits the diff
+fn histogram(
+    cls: &mut Classified,
+    mut o1: usize,
+    o2: usize,
+    mut n1: usize,
+    n2: usize,
+) {
+    loop {
+        if o1 == o2 || n1 == n2 {
+            cls.old_changed[o1..o2].fill(true);
+            cls.new_changed[n1..n2].fill(true);
+            return;
+        }
+        match histogram_lcs(cls, o1, o2, n1, n2) {
+            Lcs::Fallback => return myers(cls, (o1, o2), (n1, n2), false),
+            Lcs::None => {
+                cls.old_changed[o1..o2].fill(true);
+                cls.new_changed[n1..n2].fill(true);
+                return;
+            }
+            Lcs::Region((begin1, end1), (begin2, end2)) => {
+                histogram(cls, o1, begin1, n1, begin2);
+                (o1, n1) = (end1, end2);
+            }
+        }
+    }
+}
+
+enum Lcs {
+    /// the common lines all occur too often, use Myers' algorithm
+    Fallback,
+    None,
+    /// common region of old and new
+    Region((usize, usize), (usize, usize)),
+}
+
+fn histogram_lcs(
+    cls: &Classified,
+    o1: usize,
+    o2: usize,
+    n1: usize,
+    n2: usize,
+) -> Lcs {
+    // for each line of old, its count and the first occurrence; and for each
+    // occurrence, the next one
+    let mut records: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut next: Vec<Option<usize>> = vec![None; o2 - o1];
+    for ptr in (o1..o2).rev() {
+        let record = records.entry(cls.old[ptr]).or_insert((ptr, 0));
+        if record.1 > 0 {
+            next[ptr - o1] = Some(record.0);
+        }
+        *record = (ptr, record.1 + 1);
+    }
+    let count = |line: usize| records[&cls.old[line]].1;
+
+    let mut lowest = HISTOGRAM_MAX_CHAIN + 1;
+    let mut has_common = false;
+    let mut lcs: Option<((usize, usize), (usize, usize))> = None;
+
+    let mut b_ptr = n1;
+    while b_ptr < n2 {
+        let mut b_next = b_ptr + 1;
+        if let Some(&(first, cnt)) = records.get(&cls.new[b_ptr]) {
+            has_common = true;
+            if cnt <= lowest {
+                let mut a_ptr = first;
+                loop {
+                    let (mut as_, mut bs) = (a_ptr, b_ptr);
+                    let (mut ae, mut be) = (a_ptr, b_ptr);
+                    let mut rc = cnt;
+                    while o1 < as_
+                        && n1 < bs
+                        && cls.old[as_ - 1] == cls.new[bs - 1]
+                    {
+                        as_ -= 1;
+                        bs -= 1;
+                        if 1 < rc {
+                            rc = rc.min(count(as_));
+                        }
+                    }
+                    while ae + 1 < o2
+                        && be + 1 < n2
+                        && cls.old[ae + 1] == cls.new[be + 1]
+                    {
+                        ae += 1;
+                        be += 1;
+                        if 1 < rc {
+                            rc = rc.min(count(ae));
+                        }
+                    }
+
+                    if b_next <= be {
+                        b_next = be + 1;
+                    }
+                    let longest = lcs.map_or(0, |((b1, e1), _)| e1 - 1 - b1);
+                    if longest < ae - as_ || rc < lowest {
+                        lcs = Some(((as_, ae + 1), (bs, be + 1)));
+                        lowest = rc;
+                    }
+
+                    // next occurrence after the region
+                    let mut np = next[a_ptr - o1];
+                    while let Some(p) = np.filter(|p| *p <= ae) {
+                        np = next[p - o1];
+                    }
+                    match np {
+                        Some(p) => a_ptr = p,
+                        None => break,
+                    }
+                }
+            }
+        }
+        b_ptr = b_next;
+    }
+
+    if has_common && HISTOGRAM_MAX_CHAIN < lowest {
+        return Lcs::Fallback;
+    }
+    match lcs {
+        Some((old, new)) => Lcs::Region(old, new),
+        None => Lcs::None,
+    }
+}
+
+/// indentation of a line with tabs every 8 columns, `-1` for a blank line
+fn indent(line: &str) -> isize {
+    let mut ret = 0;
+    for c in line.chars() {
+        if !matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0b' | '\x0c') {
+            return ret;
+        } else if c == ' ' {
+            ret += 1;
+        } else if c == '\t' {
+            ret += 8 - ret % 8;
+        }
+        if ret >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+    -1
+}
+
+/// badness of splitting `lines` before `split`, as
+/// (effective indent, penalty)
+fn split_score(lines: &[&str], split: usize, score: &mut (isize, isize)) {
+    let mut pre_blank = 0;
+    let mut pre_indent = -1;
+    for line in lines[..split].iter().rev() {
+        pre_indent = indent(line);
+        if pre_indent != -1 {
+            break;
+        }
+        pre_blank += 1;
+        if pre_blank == MAX_BLANKS {
+            pre_indent = 0;
+            break;
+        }
+    }
+    let end_of_file = split >= lines.len();
+    let line_indent = lines.get(split).map_or(-1, |line| indent(line));
+    let mut post_blank = 0;
+    let mut post_indent = -1;
+    for line in lines.iter().skip(split + 1) {
+        post_indent = indent(line);
+        if post_indent != -1 {
+            break;
+        }
+        post_blank += 1;
+        if post_blank == MAX_BLANKS {
+            post_indent = 0;
+            break;
+        }
+    }
+
+    if pre_indent == -1 && pre_blank == 0 {
+        score.1 += 1;
+    }
+    if end_of_file {
+        score.1 += 21;
+    }
+    let post_blank = if line_indent == -1 { 1 + post_blank } else { 0 };
+    let total_blank = pre_blank + post_blank;
+    score.1 += -30 * total_blank + 6 * post_blank;
+
+    let indent = if line_indent != -1 {
+        line_indent
+    } else {
+        post_indent
+    };
+    let any_blanks = total_blank != 0;
+    score.0 += indent;
+
+    if indent == -1 || pre_indent == -1 || indent == pre_indent {
+    } else if indent > pre_indent {
+        score.1 += if any_blanks { 10 } else { -4 };
+    } else if post_indent != -1 && post_indent > indent {
+        score.1 += if any_blanks { 17 } else { 24 };
+    } else {
+        score.1 += if any_blanks { 17 } else { 23 };
+    }
+}
+
+/// group of changed lines in a side of the diff, `start..end`
+#[derive(Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Group {
+        let end = changed.iter().take_while(|c| **c).count();
+        Group { start: 0, end }
+    }
+
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        while changed.get(self.end) == Some(&true) {
+            self.end += 1;
+        }
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+        true
+    }
+
+    fn slide_down(&mut self, changed: &mut [bool], ids: &[usize]) -> bool {
+        if self.end < changed.len() && ids[self.start] == ids[self.end] {
+            changed[self.start] = false;
+            changed[self.end] = true;
+            self.start += 1;
+            self.end += 1;
+            while changed.get(self.end) == Some(&true) {
+                self.end += 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn slide_up(&mut self, changed: &mut [bool], ids: &[usize]) -> bool {
+        if self.start > 0 && ids[self.start - 1] == ids[self.end - 1] {
+            self.start -= 1;
+            self.end -= 1;
+            changed[self.start] = true;
+            changed[self.end] = false;
+            while self.start > 0 && changed[self.start - 1] {
+                self.start -= 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// slide the groups of changed lines of one side to line up with the
+/// changes of the other side, or else to the position splitting the text
+/// at the most natural indentation, as git's indent heuristic does
+fn compact(
+    (changed, ids, lines): (&mut [bool], &[usize], &[&str]),
+    other: &[bool],
+) {
+    let mut g = Group::first(changed);
+    let mut go = Group::first(other);
+
+    loop {
+        if g.end != g.start {
+            let mut groupsize;
+            let mut earliest_end;
+            let mut end_matching_other;
+            loop {
+                groupsize = g.end - g.start;
+                end_matching_other = None;
+
+                while g.slide_up(changed, ids) {
+                    go.previous(other);
+                }
+                earliest_end = g.end;
+                if go.end > go.start {
+                    end_matching_other = Some(g.end);
+                }
+                while g.slide_down(changed, ids) {
+                    go.next(other);
+                    if go.end > go.start {
+                        end_matching_other = Some(g.end);
+                    }
+                }
+                if groupsize == g.end - g.start {
+                    break;
+                }
+            }
+
+            if g.end == earliest_end {
+                // cannot be shifted
+            } else if end_matching_other.is_some() {
+                while go.end == go.start {
+                    g.slide_up(changed, ids);
+                    go.previous(other);
+                }
+            } else {
+                let mut shift = earliest_end
+                    .max(g.end.saturating_sub(groupsize + 1))
+                    .max(g.end.saturating_sub(INDENT_HEURISTIC_MAX_SLIDING));
+                let mut best: Option<(usize, (isize, isize))> = None;
+                while shift <= g.end {
+                    let mut score = (0, 0);
+                    split_score(lines, shift, &mut score);
+                    split_score(lines, shift - groupsize, &mut score);
+                    let better = match best {
+                        None => true,
+                        Some((_, best)) => {
+                            let indents = (score.0 > best.0) as isize
+                                - (score.0 < best.0) as isize;
+                            60 * indents + (score.1 - best.1) <= 0
+                        }
+                    };
+                    if better {
+                        best = Some((shift, score));
+                    }
+                    shift += 1;
+                }
+                if let Some((best_shift, _)) = best {
+                    while g.end > best_shift {
+                        g.slide_up(changed, ids);
+                        go.previous(other);
+                    }
+                }
+            }
+        }
+
+        if !g.next(changed) {
+            break;
+        }
+        go.next(other);
+    }
+}
+
+/// change of a line split with its newline, which the last line of a file
+/// may lack. a carriage return before the newline is kept in the content
+fn line_change(kind: Change, line: &str) -> LineChange {
+    let (content, no_newline) = match line.strip_suffix('\n') {
+        Some(content) => (content, false),
+        None => (line, true),
+    };
+    LineChange {
+        kind,
+        content: content.as_bytes().to_vec(),
+        no_newline,
+    }
+}
+
+/// build hunks from the marked lines, with `context` unchanged lines
+/// around the changes
+fn hunks(
+    old: &[&str],
+    new: &[&str],
+    cls: &Classified,
+    context: usize,
+    heading: impl Fn(&str) -> Option<String>,
+) -> Vec<DiffHunk> {
+    // changed ranges, as (old start, old end, new start, new end)
+    let mut changes: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let (mut o, mut n) = (0, 0);
+    while o < old.len() || n < new.len() {
+        if o < old.len() && cls.old_changed[o]
+            || n < new.len() && cls.new_changed[n]
+        {
+            let (os, ns) = (o, n);
+            while o < old.len() && cls.old_changed[o] {
+                o += 1;
+            }
+            while n < new.len() && cls.new_changed[n] {
+                n += 1;
+            }
+            changes.push((os, o, ns, n));
+        } else {
+            o += 1;
+            n += 1;
+        }
+    }
+
+    let mut hunks = Vec::new();
+    // heading of the previous hunk, kept when no other is found until
+    // the line the previous search started at
+    let mut func_line = String::new();
+    let mut searched = 0;
+    let mut idx = 0;
+    while idx < changes.len() {
+        // changes closer than twice the context share a hunk
+        let mut last = idx;
+        while last + 1 < changes.len()
+            && changes[last + 1].0 - changes[last].1 <= 2 * context
+        {
+            last += 1;
+        }
+
+        let (os, _, ns, _) = changes[idx];
+        let (_, oe, _, ne) = changes[last];
+        let lead = context.min(os);
+        let trail = context.min(old.len() - oe);
+        let (old_start, new_start) = (os - lead, ns - lead);
+        let (old_end, new_end) = (oe + trail, ne + trail);
+
+        let mut change = Vec::new();
+        let mut o = old_start;
+        for &(cos, coe, cns, cne) in &changes[idx..=last] {
+            change.extend(
+                old[o..cos]
+                    .iter()
+                    .map(|line| line_change(Change::Default, line)),
+            );
+            change.extend(
+                old[cos..coe]
+                    .iter()
+                    .map(|line| line_change(Change::Deleted, line)),
+            );
+            change.extend(
+                new[cns..cne]
+                    .iter()
+                    .map(|line| line_change(Change::Added, line)),
+            );
+            o = coe;
+        }
+        change.extend(
+            old[o..old_end]
+                .iter()
+                .map(|line| line_change(Change::Default, line)),
+        );
+
+        if let Some(found) = old[searched..old_start]
+            .iter()
+            .rev()
+            .find_map(|l| heading(l))
+        {
+            func_line = found;
+        }
+        searched = old_start;
+
+        let (old_len, new_len) = (old_end - old_start, new_end - new_start);
+        hunks.push(DiffHunk {
+            // an empty range is numbered after the line before it
+            old_line: if old_len == 0 {
+                old_start
+            } else {
+                old_start + 1
+            },
+            old_len,
+            new_line: if new_len == 0 {
+                new_start
+            } else {
+                new_start + 1
+            },
+            new_len,
+            heading: (!func_line.is_empty()).then(|| func_line.clone()),
+            change,
+        });
+        idx = last + 1;
+    }
+    hunks
+}
+
+impl Diff {
+    /// generate the diff from `old` to `new` with Myers' algorithm,
+    /// keeping `context` unchanged lines around each change
+    pub fn from_texts(
+        old: &str,
+        new: &str,
+        path: impl Into<PathBuf>,
+        context: usize,
+    ) -> Diff {
+        let options = GenerateOptions {
+            context,
+            ..GenerateOptions::default()
+        };
+        Self::from_texts_with(old, new, path, &options)
+    }
+
+    /// generate the diff from `old` to `new`
+    pub fn from_texts_with(
+        old: &str,
+        new: &str,
+        path: impl Into<PathBuf>,
+        options: &GenerateOptions,
+    ) -> Diff {
+        let path = path.into();
+        // lines keep their newline, so a last line without one differs
+        let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+        let mut cls = Classified::new(&old_lines, &new_lines);
+        let (o, n) = (old_lines.len(), new_lines.len());
+        match options.algorithm {
+            DiffAlgorithm::Myers => myers(&mut cls, (0, o), (0, n), false),
+            DiffAlgorithm::Minimal => myers(&mut cls, (0, o), (0, n), true),
+            DiffAlgorithm::Patience => patience(&mut cls, 0, o, 0, n),
+            DiffAlgorithm::Histogram => histogram(&mut cls, 0, o, 0, n),
+        }
+        compact(
+            (&mut cls.old_changed, &cls.old, &old_lines),
+            &cls.new_changed,
+        );
+        compact(
+            (&mut cls.new_changed, &cls.new, &new_lines),
+            &cls.old_changed,
+        );
+
+        Diff {
+            command: Some(format!(
+                "diff --git {} {}",
+                quote_path("a/", &path),
+                quote_path("b/", &path)
+            )),
+            index: None,
+            hunk: hunks(&old_lines, &new_lines, &cls, options.context, |l| {
+                let l = l.strip_suffix('\n').unwrap_or(l);
+                let l = l.strip_suffix('\r').unwrap_or(l);
+                options.funcname.as_ref()?.heading(&path, l)
+            }),
+            old_path: path.clone(),
+            path,
+            status: FileStatus::Modified,
+            old_mode: None,
+            new_mode: None,
+            similarity: None,
+            dissimilarity: None,
+            binary: None,
+            old_timestamp: None,
+            new_timestamp: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{
+        diff::*,
+        generate::{FuncName, GenerateOptions},
+    };
+
+    fn assert_roundtrip(old: &str, new: &str, context: usize) -> Diff {
+        let diff = Diff::from_texts(old, new, "tmp.rs", context);
+        println!("{:#?}", diff);
+        let applied = diff.apply(old).unwrap();
+        assert_eq!(applied.as_str(), new);
+        let reverted = diff.revert(&applied).unwrap();
+        assert_eq!(reverted.as_str(), old);
+        diff
+    }
+
+    /// length of the longest common subsequence of lines
+    fn lcs(old: &[&str], new: &[&str]) -> usize {
+        let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in 0..old.len() {
+            for j in 0..new.len() {
+                table[i + 1][j + 1] = if old[i] == new[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[old.len()][new.len()]
+    }
+
+    #[test]
+    fn test_generate_simple() {
+        let old = fs::read_to_string("test_data/simple.before").unwrap();
+        let new = fs::read_to_string("test_data/simple.after").unwrap();
+        for context in 0..5 {
+            assert_roundtrip(&old, &new, context);
+        }
+    }
+
+    #[test]
+    fn test_generate_middle() {
+        let old = fs::read_to_string("test_data/middle.before").unwrap();
+        let new = fs::read_to_string("test_data/middle.after").unwrap();
+        let diff = assert_roundtrip(&old, &new, 3);
+        let ranges: Vec<_> = diff
+            .hunk
+            .iter()
+            .map(|h| (h.old_line, h.old_len, h.new_line, h.new_len))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(16, 7, 16, 9), (25, 7, 27, 7), (262, 8, 264, 7)]
+        );
+    }
+
+    #[test]
+    fn test_generate_hunk() {
+        let diff = assert_roundtrip("a\nb\nc\nd\ne\n", "a\nb\nx\nd\ne\n", 1);
+        assert_eq!(diff.hunk.len(), 1);
+        let hunk = &diff.hunk[0];
+        assert_eq!(
+            (hunk.old_line, hunk.old_len, hunk.new_line, hunk.new_len),
+            (2, 3, 2, 3)
+        );
+        assert_eq!(hunk.to_string(), "@@ -2,3 +2,3 @@ a\n b\n-c\n+x\n d\n");
+    }
+
+    #[test]
+    fn test_generate_empty() {
+        let diff = assert_roundtrip("", "a\nb\n", 3);
+        assert_eq!(diff.hunk[0].to_string(), "@@ -0,0 +1,2 @@\n+a\n+b\n");
+        let diff = assert_roundtrip("a\nb\n", "", 3);
+        assert_eq!(diff.hunk[0].to_string(), "@@ -1,2 +0,0 @@\n-a\n-b\n");
+        let diff = assert_roundtrip("a\nb\n", "a\nx\nb\n", 0);
+        assert_eq!(diff.hunk[0].to_string(), "@@ -1,0 +2 @@ a\n+x\n");
+        assert!(assert_roundtrip("a\n", "a\n", 3).hunk.is_empty());
+    }
+
+    #[test]
+    fn test_generate_no_newline() {
+        let diff = assert_roundtrip("a\nb", "a\nc", 3);
+        assert_eq!(
+            diff.hunk[0].to_string(),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n"
+        );
+        // only the newline differs
+        let diff = assert_roundtrip("a\nb", "a\nb\n", 3);
+        assert_eq!(
+            diff.hunk[0].to_string(),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+        );
+        let diff = assert_roundtrip("a\nb\nc", "x\nb\nc", 3);
+        assert_eq!(
+            diff.hunk[0].to_string(),
+            "@@ -1,3 +1,3 @@\n-a\n+x\n b\n c\n\\ No newline at end of file\n"
+        );
+        assert_roundtrip("a", "", 3);
+        assert_roundtrip("", "a", 3);
+        assert_roundtrip("x\ny", "x\ny\nz\n", 3);
+    }
+
+    #[test]
+    fn test_generate_crlf() {
+        // only the line ending differs
+        let diff = assert_roundtrip("a\r\nb\n", "a\nb\n", 3);
+        assert_eq!(diff.hunk[0].to_string(), "@@ -1,2 +1,2 @@\n-a\r\n+a\n b\n");
+        assert_roundtrip("a\r\nb\r\nc", "a\r\nx\nc\r\n", 3);
+    }
+
+    #[test]
+    fn test_generate_minimal() {
+        // small texts of few distinct lines, compared with a plain LCS
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        for _ in 0..500 {
+            let mut text = || {
+                (0..next(12))
+                    .map(|_| ["a", "b", "c"][next(3) as usize])
+                    .collect::<Vec<_>>()
+            };
+            let (old, new) = (text(), text());
+            let old_text: String =
+                old.iter().map(|l| format!("{l}\n")).collect();
+            let new_text: String =
+                new.iter().map(|l| format!("{l}\n")).collect();
+
+            let diff = assert_roundtrip(&old_text, &new_text, 2);
+            let changed = diff
+                .hunk
+                .iter()
+                .flat_map(|h| &h.change)
+                .filter(|c| c.kind != Change::Default)
+                .count();
+            assert_eq!(changed, old.len() + new.len() - 2 * lcs(&old, &new));
+        }
+    }
+
+    #[test]
+    fn test_generate_algorithms() {
+        let old =
+            fs::read_to_string("test_data/generate/frobnitz.before").unwrap();
+        let new =
+            fs::read_to_string("test_data/generate/frobnitz.after").unwrap();
+        for (algorithm, name) in [
+            (DiffAlgorithm::Myers, "myers"),
+            (DiffAlgorithm::Patience, "patience"),
+            (DiffAlgorithm::Histogram, "histogram"),
+        ] {
+            let options = GenerateOptions {
+                context: 3,
+                algorithm,
+                funcname: Some(FuncName::default()),
+            };
+            let diff =
+                Diff::from_texts_with(&old, &new, "frobnitz.c", &options);
+            let hunks: String =
+                diff.hunk.iter().map(|hunk| hunk.to_string()).collect();
+            println!("{}", hunks);
+
+            // generated with `git diff --diff-algorithm=<name>`
+            let expected = fs::read_to_string(format!(
+                "test_data/generate/frobnitz.{name}.hunks"
+            ))
+            .unwrap();
+            assert_eq!(hunks.as_str(), expected.as_str());
+            assert_eq!(diff.apply(&old).unwrap().as_str(), new.as_str());
+        }
+    }
+
+    #[test]
+    fn test_generate_costly() {
+        // over 300 lines are added, past the cost where git cuts the search
+        // short unless the diff has to be minimal
+        let old =
+            fs::read_to_string("test_data/generate/costly.before").unwrap();
+        let new =
+            fs::read_to_string("test_data/generate/costly.after").unwrap();
+        for (algorithm, name) in [
+            (DiffAlgorithm::Myers, "myers"),
+            (DiffAlgorithm::Minimal, "minimal"),
+        ] {
+            let options = GenerateOptions {
+                context: 3,
+                algorithm,
+                funcname: Some(FuncName::default()),
+            };
+            let diff = Diff::from_texts_with(&old, &new, "costly", &options);
+            let hunks: String =
+                diff.hunk.iter().map(|hunk| hunk.to_string()).collect();
+
+            // generated with `git diff --diff-algorithm=<name>`
+            let expected = fs::read_to_string(format!(
+                "test_data/generate/costly.{name}.hunks"
+            ))
+            .unwrap();
+            assert_eq!(hunks.as_str(), expected.as_str());
+            assert_eq!(diff.apply(&old).unwrap().as_str(), new.as_str());
+        }
+    }
+
+    #[test]
+    fn test_generate_indent_heuristic() {
+        // the added block can slide, it is placed at the blank line
+        let old = "fn a() {\n}\n\nfn c() {\n}\n";
+        let new = "fn a() {\n}\n\nfn b() {\n}\n\nfn c() {\n}\n";
+        let diff = assert_roundtrip(old, new, 1);
+        assert_eq!(
+            diff.hunk[0].to_string(),
+            "@@ -3,2 +3,5 @@ fn a() {\n \n+fn b() {\n+}\n+\n fn c() {\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_funcname() {
+        let old = "mod m {\n    fn a() {\n        1\n        2\n    }\n}\n";
+        let new = "mod m {\n    fn a() {\n        1\n        3\n    }\n}\n";
+
+        let options = GenerateOptions {
+            context: 0,
+            ..Default::default()
+        };
+        let diff = Diff::from_texts_with(old, new, "m.rs", &options);
+        assert_eq!(diff.hunk[0].heading.as_deref(), Some("mod m {"));
+
+        // the pattern only applies to its extension, comments are skipped
+        let funcname = FuncName::default()
+            .pattern("rs", "!^\\s*//\n^\\s*(fn .*)$")
+            .unwrap();
+        let options = GenerateOptions {
+            context: 0,
+            funcname: Some(funcname),
+            ..Default::default()
+        };
+        let diff = Diff::from_texts_with(old, new, "m.rs", &options);
+        assert_eq!(diff.hunk[0].heading.as_deref(), Some("fn a() {"));
+        let diff = Diff::from_texts_with(old, new, "m.txt", &options);
+        assert_eq!(diff.hunk[0].heading.as_deref(), Some("mod m {"));
+
+        let options = GenerateOptions {
+            funcname: None,
+            ..Default::default()
+        };
+        let diff = Diff::from_texts_with(old, new, "m.rs", &options);
+        assert_eq!(diff.hunk[0].heading, None);
+    }
+}
@@ -1,9 +1,17 @@
 use std::path::Path;
 
+mod binary;
+
 pub mod diff;
 
+pub mod generate;
+
 pub mod parser;
 
+mod transaction;
+
+pub mod writer;
+
 pub struct DiffManager {}
 impl DiffManager {
     pub fn parse(
@@ -12,6 +20,70 @@ impl DiffManager {
     ) -> Result<diff::DiffComposition, parser::ParseError> {
         match format {
             diff::DiffFormat::GitUdiff => parser::Parser::parse_git_udiff(diff),
+            diff::DiffFormat::Unified => parser::Parser::parse_unified(diff),
+            diff::DiffFormat::Context => parser::Parser::parse_context(diff),
+            diff::DiffFormat::Normal => parser::Parser::parse_normal(diff),
+            diff::DiffFormat::Ed => Err(parser::Parser::ed_without_target()),
+        }
+    }
+
+    pub fn write(
+        format: &diff::DiffFormat,
+        comp: &diff::DiffComposition,
+    ) -> String {
+        match format {
+            diff::DiffFormat::GitUdiff => writer::Writer::write_git_udiff(comp),
+            diff::DiffFormat::Unified => writer::Writer::write_unified(comp),
+            diff::DiffFormat::Context => writer::Writer::write_context(comp),
+            diff::DiffFormat::Normal => writer::Writer::write_normal(comp),
+            diff::DiffFormat::Ed => writer::Writer::write_ed(comp),
+        }
+    }
+
+    /// parse a diff whose changed lines may be in any encoding
+    pub fn parse_bytes(
+        format: &diff::DiffFormat,
+        diff: &[u8],
+    ) -> Result<diff::DiffComposition, parser::ParseError> {
+        match format {
+            diff::DiffFormat::GitUdiff => {
+                parser::Parser::parse_git_udiff_bytes(diff)
+            }
+            diff::DiffFormat::Unified => parser::Parser::parse_unified_with(
+                diff,
+                &parser::ParseOptions::default(),
+            ),
+            diff::DiffFormat::Context => parser::Parser::parse_context_with(
+                diff,
+                &parser::ParseOptions::default(),
+            ),
+            diff::DiffFormat::Normal => parser::Parser::parse_normal_with(
+                diff,
+                &parser::ParseOptions::default(),
+            ),
+            diff::DiffFormat::Ed => Err(parser::Parser::ed_without_target()),
+        }
+    }
+
+    /// write a diff keeping the bytes of its changed lines
+    pub fn write_bytes(
+        format: &diff::DiffFormat,
+        comp: &diff::DiffComposition,
+    ) -> Vec<u8> {
+        match format {
+            diff::DiffFormat::GitUdiff => {
+                writer::Writer::write_git_udiff_bytes(comp)
+            }
+            diff::DiffFormat::Unified => {
+                writer::Writer::write_unified_bytes(comp)
+            }
+            diff::DiffFormat::Context => {
+                writer::Writer::write_context_bytes(comp)
+            }
+            diff::DiffFormat::Normal => {
+                writer::Writer::write_normal_bytes(comp)
+            }
+            diff::DiffFormat::Ed => writer::Writer::write_ed_bytes(comp),
         }
     }
 
@@ -28,4 +100,18 @@ impl DiffManager {
     ) -> Result<(), diff::DiffError> {
         comp.revert(root)
     }
+
+    pub fn merge(
+        comp: &diff::DiffComposition,
+        root: &Path,
+    ) -> Result<Vec<diff::MergeConflict>, diff::DiffError> {
+        comp.merge(root, &diff::ApplyOptions::default())
+    }
+
+    pub fn check(
+        comp: &diff::DiffComposition,
+        root: &Path,
+    ) -> diff::CheckReport {
+        comp.check(root)
+    }
 }
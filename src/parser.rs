@@ -1,20 +1,73 @@
 use {
-    crate::diff::*,
-    std::{path::PathBuf, str::FromStr},
+    crate::{binary, diff::*},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        str,
+    },
 };
 
+/// git extended header lines, between the command and the index
+const EXTENDED_HEADERS: &[&str] = &[
+    "old mode ",
+    "new mode ",
+    "deleted file mode ",
+    "new file mode ",
+    "rename from ",
+    "rename to ",
+    "copy from ",
+    "copy to ",
+    "similarity index ",
+    "dissimilarity index ",
+];
+
 pub struct Parser {}
 #[derive(Debug)]
 enum ParserState {
     Init,
     Command,
+    ExtendedHeader,
     Index,
     OriginPath,
     NewPath,
     Hunk,
     LineChange(Change),
+    NoNewline,
+    Binary,
+}
+
+/// state of the parser of context diffs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContextState {
+    Init,
+    /// after the `***` path
+    OldPath,
+    /// after the `---` path, or between hunks
+    Diff,
+    /// after the `***************` separator of a hunk
+    Separator,
+    /// in the lines of the old range of a hunk
+    Old,
+    /// in the lines of the new range of a hunk
+    New,
 }
 
+/// old or new range of a context hunk, with the lines listed under it
+#[derive(Debug, Default)]
+struct ContextRange {
+    line: String,
+    first: usize,
+    last: Option<usize>,
+    /// marker, content and whether the line lacks a newline
+    lines: Vec<(u8, Vec<u8>, bool)>,
+}
+
+/// first and last lines of a range of a normal diff or an ed script
+type EditRange = (usize, usize);
+
+/// old range, operation and added lines of a command of an ed script
+type EdEdit = (EditRange, char, Vec<Vec<u8>>);
+
 #[derive(Debug)]
 pub struct ParseError {
     kind: ParseErrorKind,
@@ -26,6 +79,33 @@ pub enum ParseErrorKind {
     InvalidLineStart,
     ExpectationFailed,
     InvalidLine,
+    /// the lines of a hunk do not add up to the lengths of its ranges
+    HunkLengthMismatch,
+}
+
+/// options of the parser
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// ignore the lengths of the hunk ranges and count them from the lines
+    /// of the hunks instead, like `git apply --recount`. hunks then end at
+    /// the first line which is not a change
+    pub recount: bool,
+    /// skip the lines around the diffs of a git diff, such as the commit
+    /// headers of `git log -p` or an email signature. the skipped text is
+    /// kept in the composition, so that writing it gives back the input
+    pub lenient: bool,
+}
+
+impl ParseError {
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+    pub fn line(&self) -> &str {
+        &self.line
+    }
 }
 
 impl Parser {
@@ -38,11 +118,20 @@ impl Parser {
                     Line::Unknown
                 }
             }
-            ParserState::Command => {
+            ParserState::Command | ParserState::ExtendedHeader => {
                 if line.starts_with("index") {
                     Line::Index
                 } else if line.starts_with(DIFF_SIGN_HEADER_ORIGIN) {
                     Line::OrignPath
+                } else if Self::is_extended_header(line) {
+                    Line::ExtendedHeader
+                } else if line.starts_with(DIFF_SIGN_BINARY)
+                    || line.starts_with(DIFF_SIGN_BINARY_STUB)
+                {
+                    Line::Binary
+                } else if line.starts_with("diff") {
+                    // a diff without hunks, such as a mode change
+                    Line::Command
                 } else {
                     Line::Unknown
                 }
@@ -50,10 +139,24 @@ impl Parser {
             ParserState::Index => {
                 if line.starts_with(DIFF_SIGN_HEADER_ORIGIN) {
                     Line::OrignPath
+                } else if line.starts_with(DIFF_SIGN_BINARY)
+                    || line.starts_with(DIFF_SIGN_BINARY_STUB)
+                {
+                    Line::Binary
+                } else if line.starts_with("diff") {
+                    Line::Command
                 } else {
                     Line::Unknown
                 }
             }
+            ParserState::Binary => {
+                // base85 has no space, data cannot be taken for a command
+                if line.starts_with("diff ") {
+                    Line::Command
+                } else {
+                    Line::BinaryData
+                }
+            }
             ParserState::OriginPath => {
                 if line.starts_with(DIFF_SIGN_HEADER_NEW) {
                     Line::NewPath
@@ -68,17 +171,9 @@ impl Parser {
                     Line::Unknown
                 }
             }
-            ParserState::Hunk => match line.split_at(1) {
-                (DIFF_SIGN_LINE_ADDED, _) => Line::LineChange(Change::Added),
-                (DIFF_SIGN_LINE_DEFAULT, _) => {
-                    Line::LineChange(Change::Default)
-                }
-                (DIFF_SIGN_LINE_DELETED, _) => {
-                    Line::LineChange(Change::Deleted)
-                }
-                _ => Line::Unknown,
-            },
-            ParserState::LineChange(_) => {
+            ParserState::Hunk => Self::parse_change_kind(line)
+                .map_or(Line::Unknown, Line::LineChange),
+            ParserState::LineChange(_) | ParserState::NoNewline => {
                 if line.starts_with("diff") {
                     Line::Command
                 } else if line.starts_with("index") {
@@ -87,30 +182,62 @@ impl Parser {
                     Line::OrignPath
                 } else if line.starts_with(DIFF_SIGN_HUNK) {
                     Line::Hunk
+                } else if line.starts_with('\\') {
+                    // the text of the marker may be translated
+                    Line::NoNewline
                 } else {
-                    match line.split_at(1) {
-                        (DIFF_SIGN_LINE_ADDED, _) => {
-                            Line::LineChange(Change::Added)
-                        }
-                        (DIFF_SIGN_LINE_DEFAULT, _) => {
-                            Line::LineChange(Change::Default)
-                        }
-                        (DIFF_SIGN_LINE_DELETED, _) => {
-                            Line::LineChange(Change::Deleted)
-                        }
-                        _ => Line::Unknown,
-                    }
+                    Self::parse_change_kind(line)
+                        .map_or(Line::Unknown, Line::LineChange)
                 }
             }
         }
     }
 
+    /// whether the lenient parser skips a line, which is not part of a
+    /// diff. after such a line only a `diff --git` line starts a diff
+    fn is_junk(
+        state: &ParserState,
+        line: &str,
+        hunk_left: (usize, usize),
+        options: &ParseOptions,
+    ) -> bool {
+        let command = line.starts_with("diff --git ");
+        match state {
+            ParserState::Init => !command,
+            // a complete hunk is followed by another one or by a new diff
+            ParserState::LineChange(_) | ParserState::NoNewline
+                if !options.recount && hunk_left == (0, 0) =>
+            {
+                !(command
+                    || line.starts_with(DIFF_SIGN_HUNK)
+                    || line.starts_with('\\'))
+            }
+            ParserState::Binary => {
+                let data = line.get(1..).zip(line.chars().next()).is_some_and(
+                    |(data, len)| {
+                        binary::line_len(len).is_some_and(|len| {
+                            len.div_ceil(4) * 5 == data.len()
+                        })
+                    },
+                );
+                let header =
+                    line.starts_with("literal ") || line.starts_with("delta ");
+                !(command || data || header || line.is_empty())
+            }
+            _ => matches!(Self::parse_line_kind(state, line), Line::Unknown),
+        }
+    }
+
     fn parse_line_content<'line>(
         line: &'line str,
         kind: &Line,
     ) -> Result<&'line str, ParseError> {
         let content = match kind {
-            Line::Command => line,
+            Line::Command
+            | Line::ExtendedHeader
+            | Line::NoNewline
+            | Line::Binary
+            | Line::BinaryData => line,
             Line::Index => {
                 line.strip_prefix("index ").ok_or_else(|| ParseError {
                     kind: ParseErrorKind::ExpectationFailed,
@@ -155,7 +282,369 @@ impl Parser {
         Ok(content)
     }
 
+    fn is_extended_header(line: &str) -> bool {
+        EXTENDED_HEADERS
+            .iter()
+            .any(|header| line.starts_with(header))
+    }
+
+    /// set the field of `diff` given by an extended header line
+    fn parse_extended_header(
+        diff: &mut Diff,
+        line: &str,
+    ) -> Result<(), ParseError> {
+        let parse_mode = |mode: &str| {
+            u32::from_str_radix(mode, 8).map_err(|e| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: format!("cannot parse file mode, {:?}", e),
+                line: line.to_string(),
+            })
+        };
+        let parse_percent = |index: &str| {
+            index
+                .strip_suffix('%')
+                .and_then(|percent| percent.parse::<u8>().ok())
+                .filter(|percent| *percent <= 100)
+                .ok_or_else(|| ParseError {
+                    kind: ParseErrorKind::ExpectationFailed,
+                    reason: "expect a percentage up to `100%`".to_string(),
+                    line: line.to_string(),
+                })
+        };
+        if let Some(mode) = line.strip_prefix("old mode ") {
+            diff.old_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("new mode ") {
+            diff.new_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            diff.status = FileStatus::Deleted;
+            diff.old_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("new file mode ") {
+            diff.status = FileStatus::Added;
+            diff.new_mode = Some(parse_mode(mode)?);
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            diff.status = FileStatus::Renamed;
+            diff.old_path = Self::parse_path(path, line)?;
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            diff.status = FileStatus::Renamed;
+            diff.path = Self::parse_path(path, line)?;
+        } else if let Some(path) = line.strip_prefix("copy from ") {
+            diff.status = FileStatus::Copied;
+            diff.old_path = Self::parse_path(path, line)?;
+        } else if let Some(path) = line.strip_prefix("copy to ") {
+            diff.status = FileStatus::Copied;
+            diff.path = Self::parse_path(path, line)?;
+        } else if let Some(index) = line.strip_prefix("similarity index ") {
+            diff.similarity = Some(parse_percent(index)?);
+        } else if let Some(index) = line.strip_prefix("dissimilarity index ") {
+            diff.dissimilarity = Some(parse_percent(index)?);
+        }
+        Ok(())
+    }
+
+    /// path of a git header running to the end of the line. git writes it
+    /// in double quotes with C escapes when it has control characters,
+    /// quotes, backslashes or bytes out of ASCII, and ends the `---` and
+    /// `+++` paths with a tab when they have a space
+    fn parse_path(src: &str, line: &str) -> Result<PathBuf, ParseError> {
+        if !src.starts_with('"') {
+            return Ok(PathBuf::from(src.strip_suffix('\t').unwrap_or(src)));
+        }
+        let (path, rest) = Self::parse_quoted_path(src, line)?;
+        if !rest.trim_end_matches('\t').is_empty() {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "unexpected text after the quoted path".to_string(),
+                line: line.to_string(),
+            })?;
+        }
+        Ok(path)
+    }
+
+    /// unquote the path starting `src`, such as `"a/caf\303\251"`, and
+    /// return it with the text after its closing quote. on unix the path
+    /// may be any bytes, elsewhere it has to be UTF-8
+    fn parse_quoted_path<'src>(
+        src: &'src str,
+        line: &str,
+    ) -> Result<(PathBuf, &'src str), ParseError> {
+        let error = |reason: &str| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: reason.to_string(),
+            line: line.to_string(),
+        };
+        let quoted =
+            src.strip_prefix('"').ok_or_else(|| error("expect `\"`"))?;
+        let mut bytes = Vec::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            let byte = match c {
+                '"' => {
+                    #[cfg(unix)]
+                    let path = {
+                        use std::os::unix::ffi::OsStringExt;
+                        PathBuf::from(std::ffi::OsString::from_vec(bytes))
+                    };
+                    #[cfg(not(unix))]
+                    let path = PathBuf::from(
+                        String::from_utf8(bytes)
+                            .map_err(|_| error("path is not valid UTF-8"))?,
+                    );
+                    return Ok((path, &quoted[i + 1..]));
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('a') => 0x07,
+                    Some('b') => 0x08,
+                    Some('t') => b'\t',
+                    Some('n') => b'\n',
+                    Some('v') => 0x0b,
+                    Some('f') => 0x0c,
+                    Some('r') => b'\r',
+                    Some('"') => b'"',
+                    Some('\\') => b'\\',
+                    // a byte in octal, such as `\303`
+                    Some(digit @ '0'..='3') => {
+                        let mut byte = digit as u8 - b'0';
+                        for _ in 0..2 {
+                            match chars.next() {
+                                Some((_, digit @ '0'..='7')) => {
+                                    byte = byte * 8 + (digit as u8 - b'0')
+                                }
+                                _ => {
+                                    Err(error("invalid octal escape in path"))?
+                                }
+                            }
+                        }
+                        byte
+                    }
+                    _ => Err(error("invalid escape in path"))?,
+                },
+                c => {
+                    bytes.extend_from_slice(
+                        c.encode_utf8(&mut [0; 4]).as_bytes(),
+                    );
+                    continue;
+                }
+            };
+            bytes.push(byte);
+        }
+        Err(error("path misses its closing quote"))
+    }
+
+    /// old and new paths of `diff --git a/old b/new`, with their prefixes.
+    /// paths which are not quoted may have spaces, they are taken as the
+    /// same path twice, or split at ` b/` for a rename, whose headers then
+    /// give the paths
+    fn parse_command_paths(
+        args: &str,
+        line: &str,
+    ) -> Result<(PathBuf, PathBuf), ParseError> {
+        if args.starts_with('"') {
+            let (old, rest) = Self::parse_quoted_path(args, line)?;
+            let new = rest.strip_prefix(' ').ok_or_else(|| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "expect a space after the old path".to_string(),
+                line: line.to_string(),
+            })?;
+            return Ok((old, Self::parse_path(new, line)?));
+        }
+        // a quoted new path runs to the end of the line
+        for (at, _) in args.match_indices(" \"") {
+            if let Ok((new, "")) =
+                Self::parse_quoted_path(&args[at + 1..], line)
+            {
+                return Ok((PathBuf::from(&args[..at]), new));
+            }
+        }
+        let half = args.len() / 2;
+        if let (Some(old), Some(new)) = (args.get(..half), args.get(half..)) {
+            let old_path = old.strip_prefix("a/");
+            if old_path.is_some() && new.strip_prefix(" b/") == old_path {
+                return Ok((PathBuf::from(old), PathBuf::from(&new[1..])));
+            }
+        }
+        args.split_once(" b/")
+            .map(|(old, new)| {
+                (PathBuf::from(old), PathBuf::from(format!("b/{new}")))
+            })
+            .ok_or_else(|| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "cannot split command's arguments".to_string(),
+                line: line.to_string(),
+            })
+    }
+
+    /// decode the `literal` or `delta` hunks following `GIT binary patch`.
+    /// each hunk is a header with the inflated size, then lines of deflated
+    /// data in base85, each one starting with its number of bytes, then a
+    /// blank line
+    fn parse_binary_patch(lines: &[&str]) -> Result<BinaryPatch, ParseError> {
+        let mut hunks = Vec::new();
+        let mut lines = lines.iter();
+        while let Some(header) = lines.next() {
+            if header.is_empty() {
+                continue;
+            }
+            let error = |reason: &str, line: &str| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: reason.to_string(),
+                line: line.to_string(),
+            };
+            let (kind, size) = if let Some(size) =
+                header.strip_prefix("literal ")
+            {
+                (BinaryKind::Literal, size)
+            } else if let Some(size) = header.strip_prefix("delta ") {
+                (BinaryKind::Delta, size)
+            } else {
+                Err(error("expect `literal` or `delta` binary hunk", header))?
+            };
+            let size = size.parse::<usize>().map_err(|e| {
+                error(&format!("cannot parse binary size, {:?}", e), header)
+            })?;
+
+            let mut deflated = Vec::new();
+            for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+                let mut chars = line.chars();
+                let decoded = chars
+                    .next()
+                    .and_then(binary::line_len)
+                    .and_then(|len| binary::decode_base85(chars.as_str(), len))
+                    .ok_or_else(|| error("invalid base85 line", line))?;
+                deflated.extend(decoded);
+            }
+            let data = binary::inflate(&deflated)
+                .filter(|data| data.len() == size)
+                .ok_or_else(|| error("cannot inflate binary hunk", header))?;
+            hunks.push(BinaryHunk { kind, data });
+        }
+
+        let mut hunks = hunks.into_iter();
+        let forward = hunks.next().ok_or_else(|| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: "binary patch without hunk".to_string(),
+            line: DIFF_SIGN_BINARY.to_string(),
+        })?;
+        let reverse = hunks.next();
+        if hunks.next().is_some() {
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidLine,
+                reason: "binary patch with more than two hunks".to_string(),
+                line: DIFF_SIGN_BINARY.to_string(),
+            })?;
+        }
+        Ok(BinaryPatch::Git { forward, reverse })
+    }
+
+    /// lines of `src` without their newline, with their text and whether
+    /// they are UTF-8. only changed lines may be in another encoding, their
+    /// sign is enough to tell their kind so their text stops at the first
+    /// byte which is not UTF-8
+    fn lines(src: &[u8]) -> impl Iterator<Item = (&[u8], &str, bool)> {
+        src.split_inclusive(|b| *b == b'\n').map(|raw| {
+            let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+            match str::from_utf8(raw) {
+                Ok(line) => (raw, line, true),
+                Err(e) => {
+                    let valid = &raw[..e.valid_up_to()];
+                    (raw, str::from_utf8(valid).unwrap_or_default(), false)
+                }
+            }
+        })
+    }
+
+    fn not_utf8(raw: &[u8]) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::InvalidLine,
+            reason: "line is not valid UTF-8".to_string(),
+            line: String::from_utf8_lossy(raw).to_string(),
+        }
+    }
+
+    /// kind of a changed line, given by its sign
+    fn parse_change_kind(line: &str) -> Option<Change> {
+        match line.get(..1)? {
+            DIFF_SIGN_LINE_ADDED => Some(Change::Added),
+            DIFF_SIGN_LINE_DEFAULT => Some(Change::Default),
+            DIFF_SIGN_LINE_DELETED => Some(Change::Deleted),
+            _ => None,
+        }
+    }
+
+    /// check the lengths of the ranges of a hunk against its lines,
+    /// or set them from its lines with `recount`
+    fn finish_hunk(
+        mut hunk: DiffHunk,
+        options: &ParseOptions,
+    ) -> Result<DiffHunk, ParseError> {
+        let count = |skip: Change| {
+            hunk.change.iter().filter(|c| c.kind != skip).count()
+        };
+        let (old_len, new_len) = (count(Change::Added), count(Change::Deleted));
+        if options.recount {
+            hunk.old_len = old_len;
+            hunk.new_len = new_len;
+        } else if (old_len, new_len) != (hunk.old_len, hunk.new_len) {
+            Err(ParseError {
+                kind: ParseErrorKind::HunkLengthMismatch,
+                reason: format!(
+                    "hunk expects {} old and {} new lines, found {old_len} and {new_len}",
+                    hunk.old_len, hunk.new_len
+                ),
+                line: hunk.header(),
+            })?;
+        }
+        Ok(hunk)
+    }
+
+    /// range of a hunk line, as `-line,len`. the length is omitted when
+    /// it is 1
+    fn parse_hunk_range(
+        range: &str,
+        sign: char,
+        line: &str,
+    ) -> Result<(usize, usize), ParseError> {
+        let parse = |value: &str, name: &str| {
+            value.parse::<usize>().map_err(|e| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: format!("cannot parse {name} to usize, {:?}", e),
+                line: line.to_string(),
+            })
+        };
+        let range = range.strip_prefix(sign).ok_or_else(|| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: format!("cannot strip `{sign}` of hunk range"),
+            line: line.to_string(),
+        })?;
+        match range.split_once(',') {
+            Some((start, len)) => {
+                Ok((parse(start, "range start")?, parse(len, "range length")?))
+            }
+            None => Ok((parse(range, "range start")?, 1)),
+        }
+    }
+
+    /// section heading written after the range of a hunk line
+    fn parse_hunk_heading(line: &str) -> Option<String> {
+        let (_, rest) = line.split_once(" @@")?;
+        rest.strip_prefix(' ').map(|heading| heading.to_string())
+    }
+
     pub fn parse_git_udiff(src: &str) -> Result<DiffComposition, ParseError> {
+        Self::parse_git_udiff_bytes(src.as_bytes())
+    }
+
+    /// parse a diff whose changed lines may be in any encoding, the other
+    /// lines must be UTF-8
+    pub fn parse_git_udiff_bytes(
+        src: &[u8],
+    ) -> Result<DiffComposition, ParseError> {
+        Self::parse_git_udiff_with(src, &ParseOptions::default())
+    }
+
+    pub fn parse_git_udiff_with(
+        src: &[u8],
+        options: &ParseOptions,
+    ) -> Result<DiffComposition, ParseError> {
         let mut state = ParserState::Init;
         // State
         //  command     diff --git a/tests/vm.rs b/tests/vm.rs
@@ -170,15 +659,55 @@ impl Parser {
         let mut diffcom = DiffComposition {
             format: DiffFormat::GitUdiff,
             diff: Vec::new(),
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
         };
 
         let mut diff_cur: Option<Diff> = None;
         let mut hunk_cur: Option<DiffHunk> = None;
+        // old and new lines the current hunk still expects
+        let mut hunk_left = (0, 0);
+        // lines of the binary patch of the current diff
+        let mut binary_cur: Option<Vec<&str>> = None;
+        // lines skipped by the lenient parser since the last diff
+        let mut skipped = String::new();
 
-        for line in src.lines() {
-            let tag = Self::parse_line_kind(&state, line);
+        for (raw, line, utf8) in Self::lines(src) {
+            if options.lenient
+                && Self::is_junk(&state, line, hunk_left, options)
+            {
+                skipped.push_str(&String::from_utf8_lossy(raw));
+                skipped.push('\n');
+                state = ParserState::Init;
+                continue;
+            }
+            // a hunk expecting more lines takes any change, even one
+            // looking like a header such as a removed `-- a`
+            let in_hunk = !options.recount
+                && hunk_left != (0, 0)
+                && matches!(
+                    state,
+                    ParserState::Hunk
+                        | ParserState::LineChange(_)
+                        | ParserState::NoNewline
+                );
+            let tag = match Self::parse_change_kind(line) {
+                Some(kind) if in_hunk => Line::LineChange(kind),
+                _ => Self::parse_line_kind(&state, line),
+            };
+            if !utf8 && !matches!(tag, Line::LineChange(_)) {
+                Err(Self::not_utf8(raw))?;
+            }
+            // changed lines keep their carriage return, it is part of the
+            // content of the file
+            let line = match tag {
+                Line::LineChange(_) => line,
+                _ => line.strip_suffix('\r').unwrap_or(line),
+            };
             state = match &tag {
                 Line::Command => ParserState::Command,
+                Line::ExtendedHeader => ParserState::ExtendedHeader,
                 Line::Index => ParserState::Index,
                 Line::OrignPath => ParserState::OriginPath,
                 Line::NewPath => ParserState::NewPath,
@@ -186,6 +715,8 @@ impl Parser {
                 Line::LineChange(change_kind) => {
                     ParserState::LineChange(*change_kind)
                 }
+                Line::NoNewline => ParserState::NoNewline,
+                Line::Binary | Line::BinaryData => ParserState::Binary,
                 Line::Unknown => Err(ParseError {
                     kind: ParseErrorKind::InvalidLineStart,
                     reason: "line starting with invalid token".to_string(),
@@ -196,66 +727,88 @@ impl Parser {
             match state {
                 ParserState::Init => unreachable!(),
                 ParserState::Command => {
+                    if diffcom.diff.is_empty() && diff_cur.is_none() {
+                        diffcom.preamble = std::mem::take(&mut skipped);
+                    } else if !skipped.is_empty() {
+                        // the current diff is pushed below
+                        let next = diffcom.diff.len()
+                            + usize::from(diff_cur.is_some());
+                        diffcom
+                            .interstitial
+                            .push((next, std::mem::take(&mut skipped)));
+                    }
                     if diff_cur.is_some() {
                         let mut diff_before = diff_cur.take().unwrap();
 
-                        if hunk_cur.is_some() {
-                            let hunk_before = hunk_cur.take().unwrap();
-                            diff_before.hunk.push(hunk_before);
+                        if let Some(hunk_before) = hunk_cur.take() {
+                            diff_before
+                                .hunk
+                                .push(Self::finish_hunk(hunk_before, options)?);
+                        }
+                        if let Some(lines) = binary_cur.take() {
+                            diff_before.binary =
+                                Some(Self::parse_binary_patch(&lines)?);
                         }
                         diffcom.diff.push(diff_before);
                     }
-                    let (file_path_a, file_path_b) = content
-                        .strip_prefix("diff --git ")
-                        .and_then(|s|s.split_once(' '))
-                        .ok_or_else(||{
-                            ParseError{
-                                kind:ParseErrorKind::ExpectationFailed,
-                                reason:"lines not starting with `diff --git ` or cannot split command's arguments".to_string(
-                                ),
-                                line:line.to_string(),
-                            }
-                        })?;
-                    let file_path_a = file_path_a
-                        .strip_prefix("a/")
-                        .ok_or_else(|| ParseError {
+                    let args = content.strip_prefix("diff --git ").ok_or_else(
+                        || ParseError {
                             kind: ParseErrorKind::ExpectationFailed,
-                            reason: "expect to path_a start with `a/`"
+                            reason: "lines not starting with `diff --git `"
                                 .to_string(),
                             line: line.to_string(),
+                        },
+                    )?;
+                    let (file_path_a, file_path_b) =
+                        Self::parse_command_paths(args, line)?;
+                    let file_path_a =
+                        file_path_a.strip_prefix("a/").map_err(|_| {
+                            ParseError {
+                                kind: ParseErrorKind::ExpectationFailed,
+                                reason: "expect to path_a start with `a/`"
+                                    .to_string(),
+                                line: line.to_string(),
+                            }
                         })?;
 
-                    let file_path_b = file_path_b
-                        .strip_prefix("b/")
-                        .ok_or_else(|| ParseError {
-                            kind: ParseErrorKind::ExpectationFailed,
-                            reason: "expect to path_b start with `b/`"
-                                .to_string(),
-                            line: line.to_string(),
-                        })?;
-                    if file_path_a != file_path_b {
-                        Err(ParseError {
-                            kind: ParseErrorKind::ExpectationFailed,
-                            reason: "file path a and b are different"
-                                .to_string(),
-                            line: line.to_string(),
+                    let file_path_b =
+                        file_path_b.strip_prefix("b/").map_err(|_| {
+                            ParseError {
+                                kind: ParseErrorKind::ExpectationFailed,
+                                reason: "expect to path_b start with `b/`"
+                                    .to_string(),
+                                line: line.to_string(),
+                            }
                         })?;
-                    }
-                    let path = PathBuf::from_str(file_path_a).map_err(|e| {
-                        ParseError {
-                            kind: ParseErrorKind::ExpectationFailed,
-                            reason: format!("cannot parse file_path, {:?}", e),
-                            line: line.to_string(),
-                        }
-                    })?;
-
                     diff_cur = Some(Diff {
-                        path,
+                        old_path: file_path_a.to_path_buf(),
+                        path: file_path_b.to_path_buf(),
                         hunk: Vec::new(),
                         command: Some(content.to_string()),
                         index: None,
+                        status: FileStatus::Modified,
+                        old_mode: None,
+                        new_mode: None,
+                        similarity: None,
+                        dissimilarity: None,
+                        binary: None,
+                        old_timestamp: None,
+                        new_timestamp: None,
                     });
                 }
+                ParserState::ExtendedHeader => match &mut diff_cur {
+                    Some(cur) => Self::parse_extended_header(cur, content)?,
+                    None => {
+                        Err(ParseError {
+                            kind: ParseErrorKind::ExpectationFailed,
+                            reason: format!(
+                                "there is no current diff {:?}",
+                                &diff_cur
+                            ),
+                            line: line.to_string(),
+                        })?;
+                    }
+                },
                 ParserState::Index => match &mut diff_cur {
                     Some(cur) => {
                         if cur.index.is_some() {
@@ -282,30 +835,35 @@ impl Parser {
                         })?;
                     }
                 },
-                ParserState::OriginPath => match &diff_cur {
-                    Some(d) => {
-                        let diff_path =
-                            d.path.to_str().ok_or_else(|| ParseError {
+                ParserState::OriginPath => match &mut diff_cur {
+                    Some(d) if content == DEV_NULL => {
+                        if d.status == FileStatus::Deleted {
+                            Err(ParseError {
                                 kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot convert diff path to str"
+                                reason: "deleted file has no old path"
                                     .to_string(),
                                 line: line.to_string(),
                             })?;
-
-                        let origin_path = content
+                        }
+                        d.status = FileStatus::Added;
+                    }
+                    Some(d) => {
+                        let origin_path = Self::parse_path(content, line)?;
+                        let origin_path = origin_path
                             .strip_prefix("a/")
-                            .ok_or_else(|| ParseError {
+                            .map_err(|_| ParseError {
                                 kind: ParseErrorKind::ExpectationFailed,
                                 reason: "old file path not start with `a/`"
                                     .to_string(),
                                 line: line.to_string(),
                             })?;
-                        if diff_path != origin_path {
+                        if d.old_path != origin_path {
                             Err(ParseError {
                                 kind: ParseErrorKind::ExpectationFailed,
                                 reason: format!(
                                     "diff path and origin path is different, [diff: {}] [origin: {}]",
-                                    diff_path, origin_path
+                                    d.old_path.display(),
+                                    origin_path.display()
                                 ),
                                 line: line.to_string(),
                             })?;
@@ -322,18 +880,21 @@ impl Parser {
                         })?;
                     }
                 },
-                ParserState::NewPath => match &diff_cur {
-                    Some(d) => {
-                        let diff_path =
-                            d.path.to_str().ok_or_else(|| ParseError {
+                ParserState::NewPath => match &mut diff_cur {
+                    Some(d) if content == DEV_NULL => {
+                        if d.status == FileStatus::Added {
+                            Err(ParseError {
                                 kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot convert diff path to str"
-                                    .to_string(),
+                                reason: "new file has no new path".to_string(),
                                 line: line.to_string(),
                             })?;
-
+                        }
+                        d.status = FileStatus::Deleted;
+                    }
+                    Some(d) => {
+                        let new_path = Self::parse_path(content, line)?;
                         let new_path =
-                            content.strip_prefix("b/").ok_or_else(|| {
+                            new_path.strip_prefix("b/").map_err(|_| {
                                 ParseError {
                                     kind: ParseErrorKind::ExpectationFailed,
                                     reason: "old file path not start with `b/`"
@@ -341,12 +902,13 @@ impl Parser {
                                     line: line.to_string(),
                                 }
                             })?;
-                        if diff_path != new_path {
+                        if d.path != new_path {
                             Err(ParseError {
                                 kind: ParseErrorKind::ExpectationFailed,
                                 reason: format!(
                                     "diff path and new path is different, [diff: {}] [new: {}]",
-                                    diff_path, new_path
+                                    d.path.display(),
+                                    new_path.display()
                                 ),
                                 line: line.to_string(),
                             })?;
@@ -366,7 +928,8 @@ impl Parser {
                 ParserState::Hunk => match &mut diff_cur {
                     Some(dc) => {
                         if let Some(hunk_before) = hunk_cur.take() {
-                            dc.hunk.push(hunk_before)
+                            dc.hunk
+                                .push(Self::finish_hunk(hunk_before, options)?)
                         }
                         let (old, new) =
                             content.split_once(' ').ok_or_else(|| {
@@ -378,82 +941,17 @@ impl Parser {
                                 }
                             })?;
                         let (old_line, old_len) =
-                            old.split_once(',').ok_or_else(|| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot split hunk old range with `,`"
-                                    .to_string(),
-                                line: line.to_string(),
-                            })?;
+                            Self::parse_hunk_range(old, '-', line)?;
                         let (new_line, new_len) =
-                            new.split_once(',').ok_or_else(|| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot split hunk new range with `,`"
-                                    .to_string(),
-                                line: line.to_string(),
-                            })?;
-
-                        let old_line = old_line
-                            .strip_prefix('-')
-                            .ok_or_else(|| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot strip `-` of old_line"
-                                    .to_string(),
-                                line: line.to_string(),
-                            })?
-                            .parse::<usize>()
-                            .map_err(|e| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: format!(
-                                    "cannot parse old_line to usize, {:?}",
-                                    e
-                                ),
-                                line: line.to_string(),
-                            })?;
-                        let old_len =
-                            old_len.parse::<usize>().map_err(|e| {
-                                ParseError {
-                                    kind: ParseErrorKind::ExpectationFailed,
-                                    reason: format!(
-                                        "cannot parse old_len to usize, {:?}",
-                                        e
-                                    ),
-                                    line: line.to_string(),
-                                }
-                            })?;
-                        let new_line = new_line
-                            .strip_prefix('+')
-                            .ok_or_else(|| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: "cannot strip `+` of new_line"
-                                    .to_string(),
-                                line: line.to_string(),
-                            })?
-                            .parse::<usize>()
-                            .map_err(|e| ParseError {
-                                kind: ParseErrorKind::ExpectationFailed,
-                                reason: format!(
-                                    "cannot parse new_line to usize, {:?}",
-                                    e
-                                ),
-                                line: line.to_string(),
-                            })?;
-                        let new_len =
-                            new_len.parse::<usize>().map_err(|e| {
-                                ParseError {
-                                    kind: ParseErrorKind::ExpectationFailed,
-                                    reason: format!(
-                                        "cannot parse new_len to usize, {:?}",
-                                        e
-                                    ),
-                                    line: line.to_string(),
-                                }
-                            })?;
+                            Self::parse_hunk_range(new, '+', line)?;
 
+                        hunk_left = (old_len, new_len);
                         hunk_cur = Some(DiffHunk {
                             old_line,
                             old_len,
                             new_line,
                             new_len,
+                            heading: Self::parse_hunk_heading(line),
                             change: Vec::new(),
                         });
                     }
@@ -461,12 +959,59 @@ impl Parser {
                         panic!("there is no current diff {:?}", &diff_cur)
                     }
                 },
+                ParserState::Binary => match (&mut diff_cur, &tag) {
+                    (Some(_), Line::Binary) if content == DIFF_SIGN_BINARY => {
+                        binary_cur = Some(Vec::new())
+                    }
+                    (Some(d), Line::Binary) => {
+                        d.binary = Some(BinaryPatch::Stub)
+                    }
+                    (Some(d), _) if binary_cur.is_none() => {
+                        Err(ParseError {
+                            kind: ParseErrorKind::InvalidLine,
+                            reason: format!(
+                                "there is no binary patch in current diff {:?}",
+                                d
+                            ),
+                            line: line.to_string(),
+                        })?;
+                    }
+                    (Some(_), _) => binary_cur.as_mut().unwrap().push(line),
+                    (None, _) => {
+                        Err(ParseError {
+                            kind: ParseErrorKind::ExpectationFailed,
+                            reason: "there is no current diff".to_string(),
+                            line: line.to_string(),
+                        })?;
+                    }
+                },
+                ParserState::NoNewline => {
+                    match hunk_cur.as_mut().and_then(|h| h.change.last_mut()) {
+                        Some(change) => change.no_newline = true,
+                        None => {
+                            Err(ParseError {
+                                kind: ParseErrorKind::ExpectationFailed,
+                                reason: "there is no line before the marker"
+                                    .to_string(),
+                                line: line.to_string(),
+                            })?;
+                        }
+                    }
+                }
                 ParserState::LineChange(kind) => match &mut hunk_cur {
                     Some(h) => {
                         let change = LineChange {
                             kind,
-                            content: content.to_string(),
+                            content: raw[1..].to_vec(),
+                            no_newline: false,
                         };
+                        let (old, new) = &mut hunk_left;
+                        if kind != Change::Added {
+                            *old = old.saturating_sub(1);
+                        }
+                        if kind != Change::Deleted {
+                            *new = new.saturating_sub(1);
+                        }
                         h.change.push(change)
                     }
                     None => {
@@ -485,7 +1030,7 @@ impl Parser {
 
         if let Some(hunk) = hunk_cur {
             match &mut diff_cur {
-                Some(c) => c.hunk.push(hunk),
+                Some(c) => c.hunk.push(Self::finish_hunk(hunk, options)?),
                 None => {
                     Err(ParseError {
                         kind: ParseErrorKind::ExpectationFailed,
@@ -495,6 +1040,10 @@ impl Parser {
                 }
             }
         }
+        if let (Some(diff), Some(lines)) = (&mut diff_cur, binary_cur) {
+            diff.binary = Some(Self::parse_binary_patch(&lines)?);
+        }
+        diffcom.trailer = skipped;
         if let Some(diff) = diff_cur {
             diffcom.diff.push(diff);
         } else {
@@ -507,29 +1056,1606 @@ impl Parser {
 
         Ok(diffcom)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use core::panic;
+    pub fn parse_unified(src: &str) -> Result<DiffComposition, ParseError> {
+        Self::parse_unified_with(src.as_bytes(), &ParseOptions::default())
+    }
 
-    use crate::{
-        diff::*,
-        parser::{Parser, ParserState},
-    };
+    /// parse the output of `diff -u` or `diff -ruN`, which has no git
+    /// headers. paths are kept as written, the text after a tab is the
+    /// timestamp of the file. the `Only in` lines of `diff -r` are skipped
+    pub fn parse_unified_with(
+        src: &[u8],
+        options: &ParseOptions,
+    ) -> Result<DiffComposition, ParseError> {
+        let mut state = ParserState::Init;
+        let mut diffcom = DiffComposition {
+            format: DiffFormat::Unified,
+            diff: Vec::new(),
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        };
 
-    const short_test_data: &str = r#"diff --git a/tests/vm.rs b/tests/vm.rs
-index 90d5af1..30044cb 100644
---- a/tests/vm.rs
-+++ b/tests/vm.rs
-@@ -16,7 +16,9 @@ fn run_vm_test(tests: Tests<Option<Object>>) {
-         let program = Parser::new(lexer).parse().unwrap();
- 
-         let mut comp = Compiler::create().unwrap();
--        comp.compile(program);
-+        if let Err(e) = comp.compile(program) {
-+            panic!("Compile error {:?}", e);
-+        }
+        // `diff` line of `diff -r`, written before the paths
+        let mut command: Option<String> = None;
+        let mut diff_cur: Option<Diff> = None;
+        let mut hunk_cur: Option<DiffHunk> = None;
+        // old and new lines the current hunk still expects
+        let mut hunk_left = (0, 0);
+
+        for (raw, line, utf8) in Self::lines(src) {
+            let in_hunk = matches!(
+                state,
+                ParserState::Hunk
+                    | ParserState::LineChange(_)
+                    | ParserState::NoNewline
+            );
+            let tag = match Self::parse_change_kind(line) {
+                Some(kind)
+                    if in_hunk && !options.recount && hunk_left != (0, 0) =>
+                {
+                    Line::LineChange(kind)
+                }
+                _ => Self::parse_unified_line_kind(&state, line),
+            };
+            if !utf8 && !matches!(tag, Line::LineChange(_)) {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = match tag {
+                Line::LineChange(_) => line,
+                _ => line.strip_suffix('\r').unwrap_or(line),
+            };
+            if !in_hunk
+                && (line.starts_with("Only in ")
+                    || line.starts_with("Common subdirectories: "))
+            {
+                continue;
+            }
+            state = match &tag {
+                Line::Command => ParserState::Command,
+                Line::OrignPath => ParserState::OriginPath,
+                Line::NewPath => ParserState::NewPath,
+                Line::Hunk => ParserState::Hunk,
+                Line::LineChange(change_kind) => {
+                    ParserState::LineChange(*change_kind)
+                }
+                Line::NoNewline => ParserState::NoNewline,
+                Line::Binary => ParserState::Binary,
+                _ => Err(ParseError {
+                    kind: ParseErrorKind::InvalidLineStart,
+                    reason: "line starting with invalid token".to_string(),
+                    line: line.to_string(),
+                })?,
+            };
+            if matches!(tag, Line::Command | Line::OrignPath | Line::Binary) {
+                if let Some(mut diff) = diff_cur.take() {
+                    if let Some(hunk) = hunk_cur.take() {
+                        diff.hunk.push(Self::finish_hunk(hunk, options)?);
+                    }
+                    diffcom.diff.push(Self::finish_unified(diff));
+                }
+            }
+
+            match tag {
+                Line::Command => command = Some(line.to_string()),
+                Line::OrignPath => {
+                    let label = Self::parse_line_content(line, &tag)?;
+                    diff_cur = Some(Self::plain_diff(command.take(), label));
+                }
+                Line::NewPath => {
+                    let label = Self::parse_line_content(line, &tag)?;
+                    // checked by the state
+                    let diff = diff_cur.as_mut().unwrap();
+                    Self::parse_new_label(diff, label, line)?;
+                }
+                Line::Hunk => {
+                    let diff = diff_cur.as_mut().unwrap();
+                    if let Some(hunk) = hunk_cur.take() {
+                        diff.hunk.push(Self::finish_hunk(hunk, options)?);
+                    }
+                    let content = Self::parse_line_content(line, &tag)?;
+                    let (old, new) =
+                        content.split_once(' ').ok_or_else(|| ParseError {
+                            kind: ParseErrorKind::ExpectationFailed,
+                            reason: "there is no space in hunk line"
+                                .to_string(),
+                            line: line.to_string(),
+                        })?;
+                    let (old_line, old_len) =
+                        Self::parse_hunk_range(old, '-', line)?;
+                    let (new_line, new_len) =
+                        Self::parse_hunk_range(new, '+', line)?;
+                    hunk_left = (old_len, new_len);
+                    hunk_cur = Some(DiffHunk {
+                        old_line,
+                        old_len,
+                        new_line,
+                        new_len,
+                        heading: Self::parse_hunk_heading(line),
+                        change: Vec::new(),
+                    });
+                }
+                Line::LineChange(kind) => {
+                    let hunk = hunk_cur.as_mut().unwrap();
+                    let (old, new) = &mut hunk_left;
+                    if kind != Change::Added {
+                        *old = old.saturating_sub(1);
+                    }
+                    if kind != Change::Deleted {
+                        *new = new.saturating_sub(1);
+                    }
+                    hunk.change.push(LineChange {
+                        kind,
+                        content: raw[1..].to_vec(),
+                        no_newline: false,
+                    });
+                }
+                Line::NoNewline => {
+                    let hunk = hunk_cur.as_mut().unwrap();
+                    match hunk.change.last_mut() {
+                        Some(change) => change.no_newline = true,
+                        None => Err(ParseError {
+                            kind: ParseErrorKind::ExpectationFailed,
+                            reason: "there is no line before the marker"
+                                .to_string(),
+                            line: line.to_string(),
+                        })?,
+                    }
+                }
+                Line::Binary => {
+                    diff_cur =
+                        Some(Self::parse_binary_stub(line, command.take())?);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(mut diff) = diff_cur else {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "there is no diff_cur at end".to_string(),
+                line: "".to_string(),
+            })?
+        };
+        if let Some(hunk) = hunk_cur {
+            diff.hunk.push(Self::finish_hunk(hunk, options)?);
+        }
+        diffcom.diff.push(Self::finish_unified(diff));
+        Ok(diffcom)
+    }
+
+    fn parse_unified_line_kind(state: &ParserState, line: &str) -> Line {
+        match state {
+            ParserState::OriginPath if line.starts_with("+++ ") => {
+                Line::NewPath
+            }
+            ParserState::NewPath if line.starts_with("@@ ") => Line::Hunk,
+            ParserState::OriginPath | ParserState::NewPath => Line::Unknown,
+            _ if line.starts_with("--- ") => Line::OrignPath,
+            _ if line.starts_with("diff ") => Line::Command,
+            _ if line.starts_with(DIFF_SIGN_BINARY_STUB) => Line::Binary,
+            ParserState::Hunk
+            | ParserState::LineChange(_)
+            | ParserState::NoNewline => {
+                if line.starts_with("@@ ") {
+                    Line::Hunk
+                } else if line.starts_with('\\') {
+                    Line::NoNewline
+                } else {
+                    Self::parse_change_kind(line)
+                        .map_or(Line::Unknown, Line::LineChange)
+                }
+            }
+            _ => Line::Unknown,
+        }
+    }
+
+    pub fn parse_context(src: &str) -> Result<DiffComposition, ParseError> {
+        Self::parse_context_with(src.as_bytes(), &ParseOptions::default())
+    }
+
+    /// parse the output of `diff -c`. paths and timestamps are read as
+    /// `parse_unified_with` does, and the two ranges of each hunk are merged
+    /// into the lines of a unified hunk
+    pub fn parse_context_with(
+        src: &[u8],
+        options: &ParseOptions,
+    ) -> Result<DiffComposition, ParseError> {
+        let mut state = ContextState::Init;
+        let mut diffcom = DiffComposition {
+            format: DiffFormat::Context,
+            diff: Vec::new(),
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        };
+
+        // `diff` line of `diff -r`, written before the paths
+        let mut command: Option<String> = None;
+        let mut diff_cur: Option<Diff> = None;
+        // heading, old and new range of the current hunk
+        let mut hunk_cur: Option<(Option<String>, ContextRange, ContextRange)> =
+            None;
+
+        for (raw, line, utf8) in Self::lines(src) {
+            let section = match (state, &mut hunk_cur) {
+                (ContextState::Old, Some((_, old, _))) => Some(old),
+                (ContextState::New, Some((_, _, new))) => Some(new),
+                _ => None,
+            };
+            // a marker and a space, the space may be trimmed from an empty line
+            let marker = match (raw.first(), raw.get(1)) {
+                (Some(b' ' | b'-' | b'+' | b'!'), Some(b' ') | None) => {
+                    section.as_ref().map(|_| raw[0])
+                }
+                _ => None,
+            };
+            if !utf8 && marker.is_none() {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = match marker {
+                Some(_) => line,
+                None => line.strip_suffix('\r').unwrap_or(line),
+            };
+            if let (Some(section), Some(marker)) = (section, marker) {
+                let allowed: &[u8] = match state {
+                    ContextState::Old => b" -!",
+                    _ => b" +!",
+                };
+                if !allowed.contains(&marker) {
+                    Err(ParseError {
+                        kind: ParseErrorKind::InvalidLineStart,
+                        reason: format!(
+                            "`{}` cannot mark a line of this range",
+                            marker as char
+                        ),
+                        line: line.to_string(),
+                    })?;
+                }
+                let content = raw.get(2..).unwrap_or_default().to_vec();
+                section.lines.push((marker, content, false));
+                continue;
+            }
+            let in_hunk = matches!(
+                state,
+                ContextState::Separator | ContextState::Old | ContextState::New
+            );
+            if line.starts_with('\\') && in_hunk {
+                let last = match (state, &mut hunk_cur) {
+                    (ContextState::Old, Some((_, old, _))) => {
+                        old.lines.last_mut()
+                    }
+                    (ContextState::New, Some((_, _, new))) => {
+                        new.lines.last_mut()
+                    }
+                    _ => None,
+                };
+                match last {
+                    Some((_, _, no_newline)) => *no_newline = true,
+                    None => Err(ParseError {
+                        kind: ParseErrorKind::ExpectationFailed,
+                        reason: "there is no line before the marker"
+                            .to_string(),
+                        line: line.to_string(),
+                    })?,
+                }
+                continue;
+            }
+            let between_files = matches!(
+                state,
+                ContextState::Init | ContextState::Diff | ContextState::New
+            );
+            if between_files
+                && (line.starts_with("Only in ")
+                    || line.starts_with("Common subdirectories: "))
+            {
+                continue;
+            }
+
+            // the hunk ends with any other line
+            if let (Some(diff), Some((heading, old, new))) = (
+                &mut diff_cur,
+                hunk_cur.take_if(|_| state == ContextState::New),
+            ) {
+                diff.hunk
+                    .push(Self::context_hunk(heading, old, new, options)?);
+            }
+            let starts_diff = between_files
+                && (line.starts_with("diff ")
+                    || line.starts_with(DIFF_SIGN_CONTEXT_ORIGIN)
+                        && !line.starts_with(DIFF_SIGN_CONTEXT_SEPARATOR)
+                    || line.starts_with(DIFF_SIGN_BINARY_STUB));
+            if starts_diff {
+                if let Some(diff) = diff_cur.take() {
+                    diffcom.diff.push(Self::finish_unified(diff));
+                }
+            }
+
+            state = match state {
+                _ if starts_diff && line.starts_with("diff ") => {
+                    command = Some(line.to_string());
+                    ContextState::Init
+                }
+                _ if starts_diff && line.starts_with(DIFF_SIGN_BINARY_STUB) => {
+                    diff_cur =
+                        Some(Self::parse_binary_stub(line, command.take())?);
+                    ContextState::Diff
+                }
+                _ if starts_diff => {
+                    let label = &line[DIFF_SIGN_CONTEXT_ORIGIN.len()..];
+                    let label = label.strip_prefix(' ').unwrap_or(label);
+                    diff_cur = Some(Self::plain_diff(command.take(), label));
+                    ContextState::OldPath
+                }
+                ContextState::OldPath
+                    if line.starts_with(DIFF_SIGN_HEADER_ORIGIN) =>
+                {
+                    let label = &line[DIFF_SIGN_HEADER_ORIGIN.len()..];
+                    let label = label.strip_prefix(' ').unwrap_or(label);
+                    // set with the state
+                    let diff = diff_cur.as_mut().unwrap();
+                    Self::parse_new_label(diff, label, line)?;
+                    ContextState::Diff
+                }
+                ContextState::Diff | ContextState::New
+                    if line.starts_with(DIFF_SIGN_CONTEXT_SEPARATOR) =>
+                {
+                    let heading = line[DIFF_SIGN_CONTEXT_SEPARATOR.len()..]
+                        .strip_prefix(' ')
+                        .map(|heading| heading.to_string());
+                    hunk_cur = Some((
+                        heading,
+                        ContextRange::default(),
+                        ContextRange::default(),
+                    ));
+                    ContextState::Separator
+                }
+                ContextState::Separator => {
+                    let range = Self::parse_context_range(line, "***", "****")?;
+                    if let Some((_, old, _)) = &mut hunk_cur {
+                        *old = range;
+                    }
+                    ContextState::Old
+                }
+                ContextState::Old
+                    if line.starts_with(DIFF_SIGN_HEADER_ORIGIN) =>
+                {
+                    let range = Self::parse_context_range(line, "---", "----")?;
+                    if let Some((_, _, new)) = &mut hunk_cur {
+                        *new = range;
+                    }
+                    ContextState::New
+                }
+                _ => Err(ParseError {
+                    kind: ParseErrorKind::InvalidLineStart,
+                    reason: "line starting with invalid token".to_string(),
+                    line: line.to_string(),
+                })?,
+            };
+        }
+
+        let Some(mut diff) = diff_cur else {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "there is no diff_cur at end".to_string(),
+                line: "".to_string(),
+            })?
+        };
+        match (state, hunk_cur) {
+            (ContextState::New, Some((heading, old, new))) => diff
+                .hunk
+                .push(Self::context_hunk(heading, old, new, options)?),
+            (_, Some(_)) => Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "the last hunk has no new range".to_string(),
+                line: "".to_string(),
+            })?,
+            _ => {}
+        }
+        diffcom.diff.push(Self::finish_unified(diff));
+        Ok(diffcom)
+    }
+
+    /// range line of a context hunk, as `*** first,last ****`
+    fn parse_context_range(
+        line: &str,
+        prefix: &str,
+        suffix: &str,
+    ) -> Result<ContextRange, ParseError> {
+        let error = |reason: String| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason,
+            line: line.to_string(),
+        };
+        let range = line
+            .strip_prefix(prefix)
+            .and_then(|range| range.strip_suffix(suffix))
+            .map(str::trim)
+            .ok_or_else(|| {
+                error(format!("expect `{prefix} range {suffix}`"))
+            })?;
+        let parse = |value: &str| {
+            value.parse::<usize>().map_err(|e| {
+                error(format!("cannot parse context range to usize, {:?}", e))
+            })
+        };
+        let (first, last) = match range.split_once(',') {
+            Some((first, last)) => (parse(first)?, Some(parse(last)?)),
+            None => (parse(range)?, None),
+        };
+        Ok(ContextRange {
+            line: line.to_string(),
+            first,
+            last,
+            lines: Vec::new(),
+        })
+    }
+
+    /// merge the old and new ranges of a context hunk
+    fn context_hunk(
+        heading: Option<String>,
+        old: ContextRange,
+        new: ContextRange,
+        options: &ParseOptions,
+    ) -> Result<DiffHunk, ParseError> {
+        // a range listing no line only has context, which the other lists
+        let context = |range: &ContextRange| {
+            range
+                .lines
+                .iter()
+                .filter(|(marker, ..)| *marker == b' ')
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let old_lines = match old.lines.is_empty() {
+            true => context(&new),
+            false => old.lines.clone(),
+        };
+        let new_lines = match new.lines.is_empty() {
+            true => context(&old),
+            false => new.lines.clone(),
+        };
+
+        let mut change = Vec::with_capacity(old_lines.len() + new_lines.len());
+        let mut push =
+            |kind, (_, content, no_newline): &(u8, Vec<u8>, bool)| {
+                change.push(LineChange {
+                    kind,
+                    content: content.clone(),
+                    no_newline: *no_newline,
+                })
+            };
+        let (mut i, mut j) = (0, 0);
+        loop {
+            // removed lines go first, then added ones
+            match (old_lines.get(i), new_lines.get(j)) {
+                (Some(line @ (b'-' | b'!', ..)), _) => {
+                    push(Change::Deleted, line);
+                    i += 1;
+                }
+                (_, Some(line @ (b'+' | b'!', ..))) => {
+                    push(Change::Added, line);
+                    j += 1;
+                }
+                (Some(line), Some(other)) if line.1 == other.1 => {
+                    push(Change::Default, line);
+                    i += 1;
+                    j += 1;
+                }
+                (None, None) => break,
+                _ => Err(ParseError {
+                    kind: ParseErrorKind::HunkLengthMismatch,
+                    reason: "the ranges do not have the same context lines"
+                        .to_string(),
+                    line: old.line.clone(),
+                })?,
+            }
+        }
+
+        let count =
+            |skip: Change| change.iter().filter(|c| c.kind != skip).count();
+        let (old_len, new_len) = (count(Change::Added), count(Change::Deleted));
+        Ok(DiffHunk {
+            old_line: Self::context_start(&old, old_len, options)?,
+            old_len,
+            new_line: Self::context_start(&new, new_len, options)?,
+            new_len,
+            heading,
+            change,
+        })
+    }
+
+    /// line a context range starts at, as in a unified range, once checked
+    /// against the number of lines it has. a single number is either one
+    /// line, or the line an empty range comes after
+    fn context_start(
+        range: &ContextRange,
+        len: usize,
+        options: &ParseOptions,
+    ) -> Result<usize, ParseError> {
+        let valid = match range.last {
+            Some(last) => (last + 1).checked_sub(range.first) == Some(len),
+            None => len == 0 || len == 1 && range.first > 0,
+        };
+        if !valid && !options.recount {
+            Err(ParseError {
+                kind: ParseErrorKind::HunkLengthMismatch,
+                reason: format!("range has {len} lines"),
+                line: range.line.clone(),
+            })?;
+        }
+        Ok(range.first)
+    }
+
+    pub fn parse_normal(src: &str) -> Result<DiffComposition, ParseError> {
+        Self::parse_normal_with(src.as_bytes(), &ParseOptions::default())
+    }
+
+    /// parse the default output of `diff`. the paths are read from the
+    /// `diff` lines of `diff -r`, and left empty for a diff of a single
+    /// file. the hunks have no context, see `DiffComposition::add_context`
+    pub fn parse_normal_with(
+        src: &[u8],
+        options: &ParseOptions,
+    ) -> Result<DiffComposition, ParseError> {
+        let mut diffcom = DiffComposition {
+            format: DiffFormat::Normal,
+            diff: Vec::new(),
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        };
+        let mut command: Option<String> = None;
+        let mut diff_cur: Option<Diff> = None;
+        // the current hunk, and whether its `---` separator was read
+        let mut hunk_cur: Option<(DiffHunk, bool)> = None;
+
+        for (raw, line, utf8) in Self::lines(src) {
+            let kind = match (raw.first(), raw.get(1)) {
+                (Some(b'<'), Some(b' ') | None) => Some(Change::Deleted),
+                (Some(b'>'), Some(b' ') | None) => Some(Change::Added),
+                _ => None,
+            };
+            if let (Some(kind), Some((hunk, separated))) = (kind, &mut hunk_cur)
+            {
+                // removed lines, the separator, then added lines
+                let in_order = match kind {
+                    Change::Deleted => !*separated && hunk.old_len > 0,
+                    _ => hunk.new_len > 0 && (*separated || hunk.old_len == 0),
+                };
+                if !in_order {
+                    Err(ParseError {
+                        kind: ParseErrorKind::ExpectationFailed,
+                        reason: "line is not in the range it marks".to_string(),
+                        line: String::from_utf8_lossy(raw).to_string(),
+                    })?;
+                }
+                hunk.change.push(LineChange {
+                    kind,
+                    content: raw.get(2..).unwrap_or_default().to_vec(),
+                    no_newline: false,
+                });
+                continue;
+            }
+            if !utf8 {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if let Some((hunk, separated)) = &mut hunk_cur {
+                if line.starts_with('\\') {
+                    match hunk.change.last_mut() {
+                        Some(change) => change.no_newline = true,
+                        None => Err(ParseError {
+                            kind: ParseErrorKind::ExpectationFailed,
+                            reason: "there is no line before the marker"
+                                .to_string(),
+                            line: line.to_string(),
+                        })?,
+                    }
+                    continue;
+                }
+                let changed = hunk.old_len > 0 && hunk.new_len > 0;
+                if line == "---" && changed && !*separated {
+                    *separated = true;
+                    continue;
+                }
+            }
+
+            // the hunk ends with any other line
+            if let (Some(diff), Some((hunk, _))) =
+                (&mut diff_cur, hunk_cur.take())
+            {
+                diff.hunk.push(Self::finish_hunk(hunk, options)?);
+            }
+            if line.starts_with("Only in ")
+                || line.starts_with("Common subdirectories: ")
+            {
+                continue;
+            }
+            if line.starts_with("diff ")
+                || line.starts_with(DIFF_SIGN_BINARY_STUB)
+            {
+                diffcom.diff.extend(diff_cur.take());
+            }
+            if line.starts_with("diff ") {
+                command = Some(line.to_string());
+            } else if line.starts_with(DIFF_SIGN_BINARY_STUB) {
+                diff_cur = Some(Self::parse_binary_stub(line, command.take())?);
+            } else if let Some(hunk) = Self::parse_normal_command(line)? {
+                if diff_cur.is_none() {
+                    diff_cur = Some(Self::edit_diff(command.take()));
+                }
+                hunk_cur = Some((hunk, false));
+            } else {
+                Err(ParseError {
+                    kind: ParseErrorKind::InvalidLineStart,
+                    reason: "line starting with invalid token".to_string(),
+                    line: line.to_string(),
+                })?;
+            }
+        }
+
+        if let (Some(diff), Some((hunk, _))) = (&mut diff_cur, hunk_cur) {
+            diff.hunk.push(Self::finish_hunk(hunk, options)?);
+        }
+        diffcom.diff.extend(diff_cur);
+        if diffcom.diff.is_empty() {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "there is no diff_cur at end".to_string(),
+                line: "".to_string(),
+            })?;
+        }
+        Ok(diffcom)
+    }
+
+    /// command of a normal diff or an ed script, as `5,7c8`, with its
+    /// ranges as their first and last lines. only normal diffs write the
+    /// new range
+    fn parse_edit_command(
+        line: &str,
+    ) -> Option<(EditRange, char, Option<EditRange>)> {
+        let at = line.find(['a', 'c', 'd'])?;
+        let range = |range: &str| {
+            let (first, last) = range.split_once(',').unwrap_or((range, range));
+            Some((first.parse().ok()?, last.parse().ok()?))
+        };
+        let new = match &line[at + 1..] {
+            "" => None,
+            new => Some(range(new)?),
+        };
+        Some((range(&line[..at])?, line[at..].chars().next()?, new))
+    }
+
+    /// hunk without lines of a command of a normal diff, or `None` when
+    /// the line is not a command
+    fn parse_normal_command(
+        line: &str,
+    ) -> Result<Option<DiffHunk>, ParseError> {
+        let Some(((first, last), op, new)) = Self::parse_edit_command(line)
+        else {
+            return Ok(None);
+        };
+        let error = || ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: format!("invalid `{op}` command"),
+            line: line.to_string(),
+        };
+        let (new_first, new_last) = new.ok_or_else(error)?;
+        let len = |first: usize, last: usize| {
+            (last + 1).checked_sub(first).filter(|len| *len > 0)
+        };
+        // the range of an addition or a deletion on the other side is the
+        // line it comes after
+        let (old_len, new_len) = match op {
+            'a' if first == last => (Some(0), len(new_first, new_last)),
+            'd' if new_first == new_last => (len(first, last), Some(0)),
+            'c' => (len(first, last), len(new_first, new_last)),
+            _ => (None, None),
+        };
+        let (Some(old_len), Some(new_len)) = (old_len, new_len) else {
+            Err(error())?
+        };
+        Ok(Some(DiffHunk {
+            old_line: first,
+            old_len,
+            new_line: new_first,
+            new_len,
+            heading: None,
+            change: Vec::new(),
+        }))
+    }
+
+    /// diff of a normal diff or an ed script, named by the paths of its
+    /// `diff` line when there is one
+    fn edit_diff(command: Option<String>) -> Diff {
+        let (old_path, path) = command
+            .as_deref()
+            .and_then(|command| {
+                let mut args = command.rsplitn(3, ' ');
+                let path = args.next()?;
+                Some((args.next()?.to_string(), path.to_string()))
+            })
+            .unwrap_or_default();
+        let mut diff = Self::plain_diff(command, &old_path);
+        diff.path = PathBuf::from(path);
+        diff
+    }
+
+    /// error of a caller which cannot give the target of an ed script,
+    /// whose old lines are read from it
+    pub(crate) fn ed_without_target() -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: "an ed script needs its target, parse it with \
+                     Parser::parse_ed(src, target)"
+                .to_string(),
+            line: String::new(),
+        }
+    }
+
+    pub fn parse_ed(
+        src: &str,
+        target: &Path,
+    ) -> Result<DiffComposition, ParseError> {
+        Self::parse_ed_bytes(src.as_bytes(), target)
+    }
+
+    /// parse an ed script of `diff -e`. the script only holds the added
+    /// lines, the removed ones are read from the old file under `target`,
+    /// or from `target` itself for the script of a single file, whose
+    /// paths are left empty. the hunks have no context, see
+    /// `DiffComposition::add_context`
+    pub fn parse_ed_bytes(
+        src: &[u8],
+        target: &Path,
+    ) -> Result<DiffComposition, ParseError> {
+        let mut diffcom = DiffComposition {
+            format: DiffFormat::Ed,
+            diff: Vec::new(),
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        };
+        let mut command: Option<String> = None;
+        let mut diff_cur: Option<Diff> = None;
+        let mut edits: Vec<EdEdit> = Vec::new();
+        // whether the lines of the last edit are read, up to a `.` line
+        let mut text = false;
+
+        for (raw, line, utf8) in Self::lines(src) {
+            if text {
+                match raw {
+                    b"." => text = false,
+                    // set with `text`
+                    _ => edits.last_mut().unwrap().2.push(raw.to_vec()),
+                }
+                continue;
+            }
+            if !utf8 {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let last = edits.last_mut().filter(|(_, op, _)| *op != 'd');
+            match line {
+                // a line of a single `.` is written `..`, then fixed
+                "s/.//" => match last.and_then(|edit| edit.2.last_mut()) {
+                    Some(added) if added == b".." => added.truncate(1),
+                    _ => Err(ParseError {
+                        kind: ParseErrorKind::ExpectationFailed,
+                        reason: "there is no `..` line to fix".to_string(),
+                        line: line.to_string(),
+                    })?,
+                },
+                // more lines after the fixed one
+                "a" if last.is_some() => text = true,
+                _ if line.starts_with("Only in ")
+                    || line.starts_with("Common subdirectories: ") => {}
+                _ if line.starts_with("diff ")
+                    || line.starts_with(DIFF_SIGN_BINARY_STUB) =>
+                {
+                    if let Some(diff) = diff_cur.take() {
+                        let edits = std::mem::take(&mut edits);
+                        diffcom.diff.push(Self::ed_diff(diff, edits, target)?);
+                    }
+                    match line.starts_with("diff ") {
+                        true => command = Some(line.to_string()),
+                        false => {
+                            diff_cur = Some(Self::parse_binary_stub(
+                                line,
+                                command.take(),
+                            )?)
+                        }
+                    }
+                }
+                _ => match Self::parse_edit_command(line) {
+                    Some((range, op, None)) => {
+                        if diff_cur.is_none() {
+                            diff_cur = Some(Self::edit_diff(command.take()));
+                        }
+                        edits.push((range, op, Vec::new()));
+                        text = op != 'd';
+                    }
+                    _ => Err(ParseError {
+                        kind: ParseErrorKind::InvalidLineStart,
+                        reason: "line starting with invalid token".to_string(),
+                        line: line.to_string(),
+                    })?,
+                },
+            }
+        }
+
+        if text {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "the lines of the last command do not end with `.`"
+                    .to_string(),
+                line: "".to_string(),
+            })?;
+        }
+        let Some(diff) = diff_cur else {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "there is no diff_cur at end".to_string(),
+                line: "".to_string(),
+            })?
+        };
+        diffcom.diff.push(Self::ed_diff(diff, edits, target)?);
+        Ok(diffcom)
+    }
+
+    /// the hunks of the edits of an ed script, with the lines they remove
+    /// read from the old file
+    fn ed_diff(
+        mut diff: Diff,
+        mut edits: Vec<EdEdit>,
+        target: &Path,
+    ) -> Result<Diff, ParseError> {
+        if edits.is_empty() {
+            return Ok(diff);
+        }
+        let path = match diff.old_path.as_os_str().is_empty() {
+            true => target.to_path_buf(),
+            false => target.join(&diff.old_path),
+        };
+        let old = fs::read(&path).map_err(|e| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: format!("cannot read {}, {e}", path.display()),
+            line: "".to_string(),
+        })?;
+        let mut old_lines: Vec<&[u8]> = old.split(|b| *b == b'\n').collect();
+        let no_newline = !old.is_empty() && !old.ends_with(b"\n");
+        if !no_newline {
+            old_lines.pop();
+        }
+
+        // the script edits the file from its end
+        edits.sort_by_key(|((first, _), ..)| *first);
+        // lines added so far, less the removed ones
+        let mut offset: isize = 0;
+        for ((first, last), op, added) in edits {
+            let (start, old_len) = match op {
+                'a' => Some((first, 0)),
+                _ => first.checked_sub(1).zip((last + 1).checked_sub(first)),
+            }
+            .ok_or_else(|| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: format!("invalid range {first},{last}"),
+                line: "".to_string(),
+            })?;
+            let removed =
+                old_lines.get(start..start + old_len).ok_or_else(|| {
+                    ParseError {
+                        kind: ParseErrorKind::ExpectationFailed,
+                        reason: format!(
+                            "{} has no lines {first} to {last}",
+                            path.display()
+                        ),
+                        line: "".to_string(),
+                    }
+                })?;
+            let mut change = Vec::with_capacity(removed.len() + added.len());
+            for (i, content) in removed.iter().enumerate() {
+                change.push(LineChange {
+                    kind: Change::Deleted,
+                    content: content.to_vec(),
+                    no_newline: no_newline && start + i + 1 == old_lines.len(),
+                });
+            }
+            let new_len = added.len();
+            change.extend(added.into_iter().map(|content| LineChange {
+                kind: Change::Added,
+                content,
+                no_newline: false,
+            }));
+            let new_start = start.saturating_add_signed(offset);
+            // an empty range is numbered by the line before it
+            let line = |start: usize, len: usize| match len {
+                0 => start,
+                _ => start + 1,
+            };
+            diff.hunk.push(DiffHunk {
+                old_line: line(start, old_len),
+                old_len,
+                new_line: line(new_start, new_len),
+                new_len,
+                heading: None,
+                change,
+            });
+            offset += new_len as isize - old_len as isize;
+        }
+        Ok(diff)
+    }
+
+    pub fn parse_mbox(src: &str) -> Result<Vec<Patch>, ParseError> {
+        Self::parse_mbox_bytes(src.as_bytes())
+    }
+
+    /// parse the emails of `git format-patch`, as a mailbox or a single
+    /// email. the diffstat after the `---` line is skipped, the diff is
+    /// parsed up to the signature
+    pub fn parse_mbox_bytes(src: &[u8]) -> Result<Vec<Patch>, ParseError> {
+        let lines: Vec<_> = Self::lines(src).collect();
+        // an email starts with a `From <commit> <date>` line after an
+        // empty line
+        let mut starts: Vec<usize> = (0..lines.len())
+            .filter(|&i| {
+                Self::is_mail_from(lines[i].1)
+                    && (i == 0 || lines[i - 1].0.is_empty())
+            })
+            .collect();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+        let mut patches = Vec::with_capacity(starts.len());
+        for (i, start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(lines.len());
+            patches.push(Self::parse_mail(&lines[*start..end])?);
+        }
+        Ok(patches)
+    }
+
+    /// first line of an email of `git format-patch`, with the commit id
+    /// and the fixed date
+    fn is_mail_from(line: &str) -> bool {
+        line.strip_prefix("From ")
+            .and_then(|rest| rest.split_once(' '))
+            .is_some_and(|(commit, date)| {
+                commit.len() == 40
+                    && commit.bytes().all(|b| b.is_ascii_hexdigit())
+                    && date == MAIL_FROM_DATE
+            })
+    }
+
+    /// line of the `-- ` before the signature, out of the hunks. a
+    /// removed `- ` line of a hunk reads the same
+    fn mail_signature(lines: &[&(&[u8], &str, bool)]) -> Option<usize> {
+        let mut hunk_left: (usize, usize) = (0, 0);
+        for (i, (raw, line, _)) in lines.iter().enumerate() {
+            if hunk_left != (0, 0) {
+                let (old, new) = &mut hunk_left;
+                match raw.first() {
+                    Some(b' ') | None => {
+                        *old = old.saturating_sub(1);
+                        *new = new.saturating_sub(1);
+                    }
+                    Some(b'-') => *old = old.saturating_sub(1),
+                    Some(b'+') => *new = new.saturating_sub(1),
+                    _ => hunk_left = (0, 0),
+                }
+            } else if *raw == MAIL_SIGNATURE.as_bytes() {
+                return Some(i);
+            } else if let Some(ranges) = line.strip_prefix("@@ ") {
+                let mut ranges = ranges.split(' ');
+                let old =
+                    ranges.next().map(|r| Self::parse_hunk_range(r, '-', line));
+                let new =
+                    ranges.next().map(|r| Self::parse_hunk_range(r, '+', line));
+                if let (Some(Ok((_, old))), Some(Ok((_, new)))) = (old, new) {
+                    hunk_left = (old, new);
+                }
+            }
+        }
+        // a hunk with wrong counts took the signature, which is only
+        // followed by the version
+        if hunk_left == (0, 0) {
+            return None;
+        }
+        lines
+            .iter()
+            .rposition(|(raw, ..)| *raw == MAIL_SIGNATURE.as_bytes())
+            .filter(|at| {
+                lines[at + 1..]
+                    .iter()
+                    .filter(|(raw, ..)| !raw.is_empty())
+                    .count()
+                    <= 1
+            })
+    }
+
+    /// one email of `git format-patch`
+    fn parse_mail(lines: &[(&[u8], &str, bool)]) -> Result<Patch, ParseError> {
+        let error = |reason: &str, line: &str| ParseError {
+            kind: ParseErrorKind::ExpectationFailed,
+            reason: reason.to_string(),
+            line: line.to_string(),
+        };
+        let mut lines = lines.iter().peekable();
+        let mut commit = None;
+        if let Some((_, line, _)) =
+            lines.next_if(|(_, l, _)| Self::is_mail_from(l))
+        {
+            commit = line.split(' ').nth(1).map(|commit| commit.to_string());
+        }
+
+        // headers up to an empty line, a line starting with a space
+        // continues the previous one
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for (raw, line, utf8) in lines.by_ref() {
+            if !utf8 {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() {
+                break;
+            }
+            match (line.starts_with([' ', '\t']), headers.last_mut()) {
+                (true, Some((_, value))) => value.push_str(line),
+                _ => {
+                    let (name, value) = line
+                        .split_once(':')
+                        .ok_or_else(|| error("expect an email header", line))?;
+                    headers.push((name.to_string(), value.trim().to_string()));
+                }
+            }
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| Self::decode_rfc2047(value))
+        };
+        let author = header("From")
+            .ok_or_else(|| error("email has no From header", ""))?;
+        let subject = header("Subject")
+            .ok_or_else(|| error("email has no Subject header", ""))?;
+        let (prefix, subject) =
+            match subject.strip_prefix('[').and_then(|s| s.split_once("] ")) {
+                Some((prefix, subject)) => {
+                    (Some(prefix.to_string()), subject.to_string())
+                }
+                None => (None, subject),
+            };
+
+        // the message ends at the `---` line, or at the diff
+        let mut message = Vec::new();
+        while let Some((raw, line, utf8)) =
+            lines.next_if(|(_, l, _)| !l.starts_with("diff --git "))
+        {
+            if !utf8 {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line == DIFF_SIGN_HEADER_ORIGIN {
+                break;
+            }
+            message.push(line);
+        }
+        while message.last().is_some_and(|line| line.is_empty()) {
+            message.pop();
+        }
+        // the diffstat
+        while lines
+            .next_if(|(_, l, _)| !l.starts_with("diff --git "))
+            .is_some()
+        {}
+
+        let rest: Vec<_> = lines.collect();
+        let signature = Self::mail_signature(&rest);
+        let (diff, signature) = match signature {
+            Some(at) => (&rest[..at], Some(&rest[at + 1..])),
+            None => (&rest[..], None),
+        };
+        let mut src = Vec::new();
+        for (raw, ..) in diff {
+            src.extend_from_slice(raw);
+            src.push(b'\n');
+        }
+        while src.ends_with(b"\n\n") {
+            src.pop();
+        }
+        if src.is_empty() {
+            Err(error("email has no diff", &subject))?;
+        }
+        let diff = Self::parse_git_udiff_bytes(&src)?;
+        let signature = signature.map(|lines| {
+            let mut lines: Vec<_> =
+                lines.iter().map(|(_, line, _)| *line).collect();
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+            lines.join("\n")
+        });
+
+        Ok(Patch {
+            commit,
+            author,
+            date: header("Date").unwrap_or_default(),
+            prefix,
+            subject,
+            message: message.join("\n"),
+            diff,
+            signature,
+        })
+    }
+
+    /// decode the `=?UTF-8?q?...?=` words of an email header, and unquote
+    /// a quoted name
+    fn decode_rfc2047(value: &str) -> String {
+        let mut out: Vec<u8> = Vec::with_capacity(value.len());
+        let mut rest = value;
+        // the space between two encoded words is not part of the text
+        let mut after_word = false;
+        while !rest.is_empty() {
+            let word = rest.strip_prefix("=?").and_then(|word| {
+                let (charset, word) = word.split_once('?')?;
+                let (encoding, word) = word.split_once('?')?;
+                let (text, rest) = word.split_once("?=")?;
+                let utf8 = charset.eq_ignore_ascii_case("utf-8")
+                    || charset.eq_ignore_ascii_case("us-ascii");
+                if !utf8 || !encoding.eq_ignore_ascii_case("q") {
+                    return None;
+                }
+                let mut decoded = Vec::with_capacity(text.len());
+                let mut bytes = text.bytes();
+                while let Some(b) = bytes.next() {
+                    match b {
+                        b'_' => decoded.push(b' '),
+                        b'=' => {
+                            let hex = [bytes.next()?, bytes.next()?];
+                            let hex = str::from_utf8(&hex).ok()?;
+                            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                        }
+                        b => decoded.push(b),
+                    }
+                }
+                Some((decoded, rest))
+            });
+            if let Some((decoded, next)) = word {
+                if after_word {
+                    let spaces = out
+                        .iter()
+                        .rev()
+                        .take_while(|b| b.is_ascii_whitespace())
+                        .count();
+                    out.truncate(out.len() - spaces);
+                }
+                out.extend(decoded);
+                rest = next;
+                after_word = true;
+                continue;
+            }
+            let c = rest.chars().next().unwrap_or_default();
+            after_word &= c.is_whitespace();
+            out.extend_from_slice(c.to_string().as_bytes());
+            rest = &rest[c.len_utf8()..];
+        }
+        let value = String::from_utf8_lossy(&out).into_owned();
+
+        // `"Doe, John" <john@example.com>`
+        let Some(quoted) = value.strip_prefix('"') else {
+            return value;
+        };
+        let mut name = String::with_capacity(value.len());
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => name.extend(chars.next()),
+                '"' => return name + chars.as_str(),
+                c => name.push(c),
+            }
+        }
+        value
+    }
+
+    pub fn parse_combined(
+        src: &str,
+    ) -> Result<CombinedComposition, ParseError> {
+        Self::parse_combined_bytes(src.as_bytes())
+    }
+
+    /// parse the diff of a merge of `git diff --cc` or `git diff -c`. each
+    /// line of a hunk starts with a sign for every parent. an empty range
+    /// is numbered by the line after it, it is moved to the line before
+    /// like in the other formats
+    pub fn parse_combined_bytes(
+        src: &[u8],
+    ) -> Result<CombinedComposition, ParseError> {
+        let mut com = CombinedComposition { diff: Vec::new() };
+        // lines the current hunk still expects in each parent, then in
+        // the merge
+        let mut left: Vec<usize> = Vec::new();
+        for (raw, line, utf8) in Self::lines(src) {
+            let in_hunk = left.iter().any(|n| *n > 0);
+            let hunk = com
+                .diff
+                .last_mut()
+                .and_then(|diff| diff.hunk.last_mut())
+                .filter(|_| in_hunk);
+            if let Some(hunk) = hunk {
+                let parents = hunk.old_range.len();
+                let signs = raw.get(..parents).and_then(|signs| {
+                    signs
+                        .iter()
+                        .map(|sign| match sign {
+                            b'+' => Some(Change::Added),
+                            b'-' => Some(Change::Deleted),
+                            b' ' => Some(Change::Default),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>()
+                });
+                let Some(kind) = signs else {
+                    let (old, new) = left.split_at(parents);
+                    Err(ParseError {
+                        kind: ParseErrorKind::HunkLengthMismatch,
+                        reason: format!(
+                            "hunk still expects {old:?} old and {} new lines",
+                            new[0]
+                        ),
+                        line: line.to_string(),
+                    })?
+                };
+                let removed = kind.contains(&Change::Deleted);
+                if removed && kind.contains(&Change::Added) {
+                    Err(ParseError {
+                        kind: ParseErrorKind::InvalidLine,
+                        reason: "line both added and removed".to_string(),
+                        line: line.to_string(),
+                    })?;
+                }
+                for (left, kind) in left.iter_mut().zip(&kind) {
+                    if *kind == Change::Deleted
+                        || (!removed && *kind == Change::Default)
+                    {
+                        *left = left.saturating_sub(1);
+                    }
+                }
+                if !removed {
+                    left[parents] = left[parents].saturating_sub(1);
+                }
+                hunk.change.push(CombinedChange {
+                    kind,
+                    content: raw[parents..].to_vec(),
+                    no_newline: false,
+                });
+                continue;
+            }
+
+            if !utf8 {
+                Err(Self::not_utf8(raw))?;
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let error = |kind: ParseErrorKind, reason: &str| ParseError {
+                kind,
+                reason: reason.to_string(),
+                line: line.to_string(),
+            };
+            let parse_mode = |mode: &str| {
+                u32::from_str_radix(mode, 8).map_err(|e| ParseError {
+                    kind: ParseErrorKind::ExpectationFailed,
+                    reason: format!("cannot parse file mode, {:?}", e),
+                    line: line.to_string(),
+                })
+            };
+            let path = line
+                .strip_prefix("diff --cc ")
+                .or_else(|| line.strip_prefix("diff --combined "));
+            if let Some(path) = path {
+                com.diff.push(CombinedDiff {
+                    command: Some(line.to_string()),
+                    index: None,
+                    path: Self::parse_path(path, line)?,
+                    status: FileStatus::Modified,
+                    old_mode: Vec::new(),
+                    new_mode: None,
+                    hunk: Vec::new(),
+                });
+                continue;
+            }
+            let Some(diff) = com.diff.last_mut() else {
+                Err(error(
+                    ParseErrorKind::InvalidLineStart,
+                    "expect `diff --cc` or `diff --combined`",
+                ))?
+            };
+            if let Some(index) = line.strip_prefix("index ") {
+                diff.index = Some(index.to_string());
+            } else if let Some(modes) = line.strip_prefix("mode ") {
+                let (old, new) = modes.split_once("..").ok_or_else(|| {
+                    error(
+                        ParseErrorKind::ExpectationFailed,
+                        "expect modes as `old,old..new`",
+                    )
+                })?;
+                diff.old_mode =
+                    old.split(',').map(parse_mode).collect::<Result<_, _>>()?;
+                diff.new_mode = Some(parse_mode(new)?);
+            } else if let Some(mode) = line.strip_prefix("new file mode ") {
+                diff.status = FileStatus::Added;
+                diff.new_mode = Some(parse_mode(mode)?);
+            } else if let Some(modes) = line.strip_prefix("deleted file mode ")
+            {
+                diff.status = FileStatus::Deleted;
+                diff.old_mode = modes
+                    .split(',')
+                    .map(parse_mode)
+                    .collect::<Result<_, _>>()?;
+            } else if line.starts_with("--- ") || line.starts_with("+++ ") {
+                // the paths are those of the command
+            } else if line.starts_with("@@@") {
+                let signs = line.bytes().take_while(|b| *b == b'@').count();
+                let (marker, rest) = line.split_at(signs);
+                let (ranges, heading) = rest
+                    .strip_prefix(' ')
+                    .and_then(|rest| rest.split_once(&format!(" {marker}")))
+                    .ok_or_else(|| {
+                        error(
+                            ParseErrorKind::ExpectationFailed,
+                            "expect ranges between the `@` signs",
+                        )
+                    })?;
+                let ranges: Vec<&str> = ranges.split(' ').collect();
+                if ranges.len() != signs {
+                    Err(error(
+                        ParseErrorKind::ExpectationFailed,
+                        "expect a range for each parent and for the merge",
+                    ))?;
+                }
+                let (new, old) = ranges.split_last().unwrap();
+                let empty_before = |(start, len): (usize, usize)| match len {
+                    0 => (start.saturating_sub(1), len),
+                    _ => (start, len),
+                };
+                let old_range = old
+                    .iter()
+                    .map(|range| Self::parse_hunk_range(range, '-', line))
+                    .map(|range| range.map(empty_before))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (new_line, new_len) =
+                    empty_before(Self::parse_hunk_range(new, '+', line)?);
+                left = old_range.iter().map(|(_, len)| *len).collect();
+                left.push(new_len);
+                diff.hunk.push(CombinedHunk {
+                    old_range,
+                    new_line,
+                    new_len,
+                    heading: heading.strip_prefix(' ').map(str::to_string),
+                    change: Vec::new(),
+                });
+            } else if line.starts_with('\\') {
+                let last =
+                    diff.hunk.last_mut().and_then(|h| h.change.last_mut());
+                match last {
+                    Some(change) => change.no_newline = true,
+                    None => Err(error(
+                        ParseErrorKind::ExpectationFailed,
+                        "there is no line before the marker",
+                    ))?,
+                }
+            } else {
+                Err(error(
+                    ParseErrorKind::InvalidLineStart,
+                    "line starting with invalid token",
+                ))?;
+            }
+        }
+        if left.iter().any(|n| *n > 0) {
+            Err(ParseError {
+                kind: ParseErrorKind::HunkLengthMismatch,
+                reason: "the last hunk misses lines".to_string(),
+                line: String::new(),
+            })?;
+        }
+        if com.diff.is_empty() {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "there is no combined diff".to_string(),
+                line: String::new(),
+            })?;
+        }
+        Ok(com)
+    }
+
+    /// diff not made by git, from its old path
+    fn plain_diff(command: Option<String>, label: &str) -> Diff {
+        let (old_path, old_timestamp) = Self::parse_label(label);
+        Diff {
+            command,
+            index: None,
+            path: old_path.clone(),
+            old_path,
+            old_timestamp,
+            new_timestamp: None,
+            status: FileStatus::Modified,
+            old_mode: None,
+            new_mode: None,
+            similarity: None,
+            dissimilarity: None,
+            binary: None,
+            hunk: Vec::new(),
+        }
+    }
+
+    /// set the new path of a diff not made by git. `/dev/null` stands for
+    /// a missing file
+    fn parse_new_label(
+        diff: &mut Diff,
+        label: &str,
+        line: &str,
+    ) -> Result<(), ParseError> {
+        let (path, new_timestamp) = Self::parse_label(label);
+        if diff.old_path.as_os_str() == DEV_NULL {
+            diff.status = FileStatus::Added;
+            diff.old_path = path.clone();
+        }
+        if path.as_os_str() == DEV_NULL {
+            if diff.status == FileStatus::Added {
+                Err(ParseError {
+                    kind: ParseErrorKind::ExpectationFailed,
+                    reason: "both paths are /dev/null".to_string(),
+                    line: line.to_string(),
+                })?;
+            }
+            diff.status = FileStatus::Deleted;
+        } else {
+            diff.path = path;
+        }
+        diff.new_timestamp = new_timestamp;
+        Ok(())
+    }
+
+    /// path and timestamp of a `---` or `+++` line, split by a tab
+    fn parse_label(label: &str) -> (PathBuf, Option<String>) {
+        match label.split_once('\t') {
+            Some((path, timestamp)) => {
+                (PathBuf::from(path), Some(timestamp.to_string()))
+            }
+            None => (PathBuf::from(label), None),
+        }
+    }
+
+    /// `Binary files x and y differ` of a diff not made by git
+    fn parse_binary_stub(
+        line: &str,
+        command: Option<String>,
+    ) -> Result<Diff, ParseError> {
+        let (old_path, path) = line
+            .strip_prefix(DIFF_SIGN_BINARY_STUB)
+            .and_then(|paths| paths.strip_suffix(" differ"))
+            .and_then(|paths| paths.split_once(" and "))
+            .ok_or_else(|| ParseError {
+                kind: ParseErrorKind::ExpectationFailed,
+                reason: "expect `Binary files x and y differ`".to_string(),
+                line: line.to_string(),
+            })?;
+        let (status, old_path, path) = match (old_path, path) {
+            (DEV_NULL, path) => (FileStatus::Added, path, path),
+            (old_path, DEV_NULL) => (FileStatus::Deleted, old_path, old_path),
+            (old_path, path) => (FileStatus::Modified, old_path, path),
+        };
+        Ok(Diff {
+            command,
+            index: None,
+            old_path: PathBuf::from(old_path),
+            path: PathBuf::from(path),
+            old_timestamp: None,
+            new_timestamp: None,
+            status,
+            old_mode: None,
+            new_mode: None,
+            similarity: None,
+            dissimilarity: None,
+            binary: Some(BinaryPatch::Stub),
+            hunk: Vec::new(),
+        })
+    }
+
+    /// `diff -N` writes a missing file as an empty one dated at the epoch
+    fn finish_unified(mut diff: Diff) -> Diff {
+        let epoch = |timestamp: &Option<String>| {
+            timestamp.as_deref().is_some_and(Self::is_epoch)
+        };
+        if diff.status == FileStatus::Modified && diff.binary.is_none() {
+            if epoch(&diff.old_timestamp)
+                && diff.hunk.iter().all(|h| h.old_len == 0)
+            {
+                diff.status = FileStatus::Added;
+            } else if epoch(&diff.new_timestamp)
+                && diff.hunk.iter().all(|h| h.new_len == 0)
+            {
+                diff.status = FileStatus::Deleted;
+            }
+        }
+        diff
+    }
+
+    /// whether a timestamp such as `1970-01-01 01:00:00.000000000 +0100`
+    /// is the epoch in its time zone. the timestamps of `diff -c`, as
+    /// `Thu Jan  1 01:00:00 1970`, have no zone, they are the epoch in
+    /// some zone between -1200 and +1400
+    pub(crate) fn is_epoch(timestamp: &str) -> bool {
+        let minutes = |hours: &str, minutes: &str| {
+            Some(
+                hours.parse::<i32>().ok()? * 60
+                    + minutes.parse::<i32>().ok()?,
+            )
+        };
+        // minutes since the start of the day, of a time on the minute
+        let time = |time: &str| match time.split(':').collect::<Vec<_>>()[..] {
+            [hours, mins, secs] => minutes(hours, mins).filter(|_| {
+                secs.split('.').all(|part| part.bytes().all(|b| b == b'0'))
+            }),
+            _ => None,
+        };
+        let parts: Vec<_> = timestamp.split_whitespace().collect();
+        if let [_, month, day, clock, year] = parts[..] {
+            let day = match (month, day, year) {
+                ("Jan", "1", "1970") => 0,
+                ("Dec", "31", "1969") => -24 * 60,
+                _ => return false,
+            };
+            return time(clock).is_some_and(|time| {
+                let zone = day + time;
+                (-12 * 60..=14 * 60).contains(&zone) && zone % 15 == 0
+            });
+        }
+        let [date, clock, zone] = parts[..] else {
+            return false;
+        };
+        let zone = match zone.split_at_checked(1) {
+            Some((sign @ ("+" | "-"), hhmm)) if hhmm.len() == 4 => {
+                let zone = minutes(&hhmm[..2], &hhmm[2..]);
+                zone.map(|z| if sign == "-" { -z } else { z })
+            }
+            _ => None,
+        };
+        let day = match date {
+            "1970-01-01" => 0,
+            "1969-12-31" => -24 * 60,
+            _ => return false,
+        };
+        matches!((time(clock), zone), (Some(time), Some(zone)) if day + time == zone)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::panic;
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::{
+        diff::*,
+        parser::{ParseErrorKind, ParseOptions, Parser, ParserState},
+        writer::Writer,
+    };
+
+    const SHORT_TEST_DATA: &str = r#"diff --git a/tests/vm.rs b/tests/vm.rs
+index 90d5af1..30044cb 100644
+--- a/tests/vm.rs
++++ b/tests/vm.rs
+@@ -16,7 +16,9 @@ fn run_vm_test(tests: Tests<Option<Object>>) {
+         let program = Parser::new(lexer).parse().unwrap();
+ 
+         let mut comp = Compiler::create().unwrap();
+-        comp.compile(program);
++        if let Err(e) = comp.compile(program) {
++            panic!("Compile error {:?}", e);
++        }
          let bytecode = comp.bytecode().unwrap();
  
          println!("Bytecode\n{}", bytecode.to_string());
@@ -556,10 +2682,11 @@ index 90d5af1..30044cb 100644
     #[test]
     fn test_parse_linestart() {
         let mut state = ParserState::Init;
-        for line in short_test_data.lines() {
+        for line in SHORT_TEST_DATA.lines() {
             let tag = Parser::parse_line_kind(&state, line);
             state = match &tag {
                 Line::Command => ParserState::Command,
+                Line::ExtendedHeader => ParserState::ExtendedHeader,
                 Line::Index => ParserState::Index,
                 Line::OrignPath => ParserState::OriginPath,
                 Line::NewPath => ParserState::NewPath,
@@ -567,6 +2694,8 @@ index 90d5af1..30044cb 100644
                 Line::LineChange(change_kind) => {
                     ParserState::LineChange(*change_kind)
                 }
+                Line::NoNewline => ParserState::NoNewline,
+                Line::Binary | Line::BinaryData => ParserState::Binary,
                 Line::Unknown => panic!("Unknown line start"),
             };
 
@@ -576,7 +2705,596 @@ index 90d5af1..30044cb 100644
 
     #[test]
     fn test_parse_udiff() {
-        let com = Parser::parse_git_udiff(short_test_data).unwrap();
+        let com = Parser::parse_git_udiff(SHORT_TEST_DATA).unwrap();
         println!("{:#?}", com);
     }
+
+    #[test]
+    fn test_parse_extended_header() {
+        let src =
+            fs::read_to_string("test_data/composition/files.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| {
+                (d.path.to_str().unwrap(), d.status, d.old_mode, d.new_mode)
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("added", FileStatus::Added, None, Some(0o100644)),
+                ("empty", FileStatus::Added, None, Some(0o100644)),
+                (
+                    "mode_only",
+                    FileStatus::Modified,
+                    Some(0o100644),
+                    Some(0o100755)
+                ),
+                ("removed", FileStatus::Deleted, Some(0o100644), None),
+                (
+                    "script",
+                    FileStatus::Modified,
+                    Some(0o100644),
+                    Some(0o100755)
+                ),
+            ]
+        );
+        assert!(com.diff[1].hunk.is_empty());
+        assert_eq!(com.diff[3].hunk.len(), 1);
+
+        // a `/dev/null` path alone is enough to create or delete
+        let src =
+            "diff --git a/x b/x\n--- a/x\n+++ /dev/null\n@@ -1,1 +0,0 @@\n-x\n";
+        let com = Parser::parse_git_udiff(src).unwrap();
+        assert_eq!(com.diff[0].status, FileStatus::Deleted);
+    }
+
+    #[test]
+    fn test_parse_hunk_range() {
+        let src = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n@@ -0,0 +2,3 @@ main\n+c\n+d\n+e\n";
+        let com = Parser::parse_git_udiff(src).unwrap();
+        let ranges: Vec<_> = com.diff[0]
+            .hunk
+            .iter()
+            .map(|h| (h.old_line, h.old_len, h.new_line, h.new_len))
+            .collect();
+        assert_eq!(ranges, vec![(1, 1, 1, 1), (0, 0, 2, 3)]);
+        assert_eq!(com.diff[0].hunk[1].heading.as_deref(), Some("main"));
+
+        for range in ["@@ -1, +1 @@", "@@ 1 +1 @@", "@@ -1 +x @@"] {
+            let src =
+                format!("diff --git a/x b/x\n--- a/x\n+++ b/x\n{range}\n");
+            assert!(Parser::parse_git_udiff(&src).is_err(), "{range}");
+        }
+    }
+
+    #[test]
+    fn test_parse_hunk_length() {
+        let header = "diff --git a/x b/x\n--- a/x\n+++ b/x\n";
+        // truncated, then with an extra line
+        for (body, counted) in [
+            ("@@ -1,3 +1,3 @@\n a\n-b\n+c\n", (2, 2)),
+            ("@@ -1,2 +1,2 @@\n a\n-b\n+c\n d\n", (3, 3)),
+        ] {
+            let src = format!("{header}{body}");
+            let err = Parser::parse_git_udiff(&src).unwrap_err();
+            assert!(matches!(err.kind(), ParseErrorKind::HunkLengthMismatch));
+            assert_eq!(err.line(), body.lines().next().unwrap());
+
+            let options = ParseOptions {
+                recount: true,
+                ..Default::default()
+            };
+            let com =
+                Parser::parse_git_udiff_with(src.as_bytes(), &options).unwrap();
+            let hunk = &com.diff[0].hunk[0];
+            assert_eq!((hunk.old_len, hunk.new_len), counted);
+        }
+
+        // lines left in the hunk are changes, whatever they look like
+        let src = format!("{header}@@ -1,3 +1,1 @@\n--- a\n-diff b\n c\n");
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let hunk = &com.diff[0].hunk[0];
+        assert_eq!(hunk.change.len(), 3);
+        assert_eq!(hunk.change[0].content, b"-- a");
+        assert_eq!(hunk.change[1].content, b"diff b");
+    }
+
+    #[test]
+    fn test_parse_unified() {
+        let src = fs::read_to_string("test_data/unified/tree.diffs").unwrap();
+        let com = Parser::parse_unified(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| {
+                (
+                    d.old_path.to_str().unwrap(),
+                    d.path.to_str().unwrap(),
+                    d.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("old/added", "new/added", FileStatus::Added),
+                ("old/bin", "new/bin", FileStatus::Modified),
+                ("old/removed", "new/removed", FileStatus::Deleted),
+                ("old/src/main.rs", "new/src/main.rs", FileStatus::Modified),
+                ("old/tail", "new/tail", FileStatus::Modified),
+            ]
+        );
+        let main = &com.diff[3];
+        assert_eq!(
+            main.command.as_deref(),
+            Some("diff -ruN old/src/main.rs new/src/main.rs")
+        );
+        assert_eq!(
+            main.old_timestamp.as_deref(),
+            Some("2024-03-01 10:00:00.000000000 +0000")
+        );
+        assert_eq!(main.hunk[0].change.len(), 5);
+        assert!(matches!(com.diff[1].binary, Some(BinaryPatch::Stub)));
+        assert!(com.diff[1].command.is_none());
+        assert!(com.diff[4].hunk[0].change[1].no_newline);
+
+        let src = fs::read_to_string("test_data/unified/single.diffs").unwrap();
+        let com = Parser::parse_unified(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| {
+                (
+                    d.old_path.to_str().unwrap(),
+                    d.path.to_str().unwrap(),
+                    d.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("main.rs.orig", "main.rs", FileStatus::Modified),
+                ("added", "added", FileStatus::Added),
+                ("stale", "nonexist", FileStatus::Deleted),
+            ]
+        );
+
+        // git headers do not belong to this format
+        let src = fs::read_to_string("test_data/simple.diffs").unwrap();
+        assert!(Parser::parse_unified(&src).is_err());
+    }
+
+    #[test]
+    fn test_parse_context() {
+        let src = fs::read_to_string("test_data/context/tree.diffs").unwrap();
+        let com = Parser::parse_context(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| (d.path.to_str().unwrap(), d.status, d.hunk.len()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("new/gone", FileStatus::Deleted, 1),
+                ("new/middle.rs", FileStatus::Modified, 3),
+                ("new/sub/fresh", FileStatus::Added, 1),
+                ("new/sub/list", FileStatus::Modified, 2),
+            ]
+        );
+        let gone = &com.diff[0].hunk[0];
+        assert_eq!((gone.old_line, gone.old_len), (1, 3));
+        assert_eq!((gone.new_line, gone.new_len), (0, 0));
+        // a `!` run becomes its removed lines then its added lines
+        let kinds: Vec<_> =
+            com.diff[1].hunk[2].change.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds[3..6],
+            [Change::Deleted, Change::Deleted, Change::Added]
+        );
+        assert_eq!(com.diff[1].hunk[2].old_len, 8);
+        assert_eq!(com.diff[1].hunk[2].new_len, 7);
+        // the old range only has context and is not listed
+        let list = &com.diff[3].hunk[1];
+        assert_eq!((list.old_line, list.old_len), (6, 3));
+        assert_eq!((list.new_line, list.new_len), (7, 4));
+        assert!(list.change[3].no_newline);
+        assert!(!list.change[2].no_newline);
+
+        let src = fs::read_to_string("test_data/context/single.diffs").unwrap();
+        let com = Parser::parse_context(&src).unwrap();
+        let hunk = &com.diff[0].hunk[0];
+        assert_eq!(hunk.heading.as_deref(), Some("fn main() {"));
+        assert_eq!(hunk.change.len(), 11);
+        assert!(hunk.change[7].no_newline);
+
+        // the ranges are checked against the lines listed under them
+        let bad = src.replace("*** 5,12 ****", "*** 5,13 ****");
+        let err = Parser::parse_context(&bad).unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::HunkLengthMismatch));
+        let options = ParseOptions {
+            recount: true,
+            ..Default::default()
+        };
+        let com = Parser::parse_context_with(bad.as_bytes(), &options).unwrap();
+        assert_eq!(com.diff[0].hunk[0].old_len, 8);
+        // the context of both ranges has to match
+        let bad = src.replacen("      let w = 4;", "      let w = 5;", 1);
+        assert!(Parser::parse_context(&bad).is_err());
+        let bad = src.replacen("-     let y", "+     let y", 1);
+        assert!(Parser::parse_context(&bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_normal() {
+        let src = fs::read_to_string("test_data/normal/tree.diffs").unwrap();
+        let com = Parser::parse_normal(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| {
+                (
+                    d.old_path.to_str().unwrap(),
+                    d.path.to_str().unwrap(),
+                    d.hunk.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("old/gone", "new/gone", 1),
+                ("old/middle.rs", "new/middle.rs", 3),
+                ("old/sub/fresh", "new/sub/fresh", 1),
+                ("old/sub/list", "new/sub/list", 2),
+            ]
+        );
+        let gone = &com.diff[0].hunk[0];
+        assert_eq!((gone.old_line, gone.old_len), (1, 3));
+        assert_eq!((gone.new_line, gone.new_len), (0, 0));
+        let middle = &com.diff[1].hunk[2];
+        assert_eq!((middle.old_line, middle.old_len), (265, 2));
+        assert_eq!((middle.new_line, middle.new_len), (267, 1));
+        let list = &com.diff[3].hunk[1];
+        assert_eq!((list.old_line, list.old_len), (8, 0));
+        assert!(list.change[0].no_newline);
+
+        // a diff of a single file has no paths
+        let src = fs::read_to_string("test_data/normal/single.diffs").unwrap();
+        let com = Parser::parse_normal(&src).unwrap();
+        assert_eq!(com.diff.len(), 1);
+        assert_eq!(com.diff[0].path.as_os_str(), "");
+        assert_eq!(com.diff[0].hunk[1].change.len(), 5);
+        assert!(com.diff[0].hunk[1].change[1].no_newline);
+
+        let bad = src.replace("11,12c10,12", "11,13c10,12");
+        let err = Parser::parse_normal(&bad).unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::HunkLengthMismatch));
+        let bad = src.replace("8d7", "8a7");
+        assert!(Parser::parse_normal(&bad).is_err());
+        let bad = src.replace("---\n", "");
+        assert!(Parser::parse_normal(&bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_ed() {
+        // the removed lines are read from the target
+        let src = fs::read_to_string("test_data/ed/single.diffs").unwrap();
+        let target = Path::new("test_data/normal/main.rs.orig");
+        let com = Parser::parse_ed(&src, target).unwrap();
+        let normal =
+            fs::read_to_string("test_data/normal/single.diffs").unwrap();
+        let normal = Parser::parse_normal(&normal).unwrap();
+        let hunks = |com: &DiffComposition| {
+            com.diff[0]
+                .hunk
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hunks(&com)[0], hunks(&normal)[0]);
+        // `diff -e` does not tell that the old file lacks a last newline
+        let hunk = &com.diff[0].hunk[1];
+        assert_eq!((hunk.old_line, hunk.old_len), (11, 1));
+        assert_eq!((hunk.new_line, hunk.new_len), (10, 2));
+        assert!(Parser::parse_ed(&src, Path::new("test_data/none")).is_err());
+
+        // the old tree of the script, its middle.rs is middle.before
+        let root = Path::new("test_data/ed_tree_parse");
+        fs::create_dir_all(root.join("old/sub")).expect("failed to create dir");
+        fs::copy("test_data/middle.before", root.join("old/middle.rs"))
+            .expect("failed to copy");
+        fs::copy("test_data/ed/old/sub/list", root.join("old/sub/list"))
+            .expect("failed to copy");
+        let src = fs::read_to_string("test_data/ed/tree.diffs").unwrap();
+        let com = Parser::parse_ed(&src, root).unwrap();
+        fs::remove_dir_all(root).expect("failed to remove dir");
+        assert_eq!(com.diff.len(), 2);
+        assert_eq!(com.diff[0].path, Path::new("new/middle.rs"));
+        let hunk = &com.diff[0].hunk[2];
+        assert_eq!((hunk.old_line, hunk.old_len), (265, 2));
+        assert_eq!((hunk.new_line, hunk.new_len), (267, 1));
+        assert_eq!(hunk.change[1].content, b"fun()");
+        let hunk = &com.diff[1].hunk[1];
+        assert_eq!((hunk.old_line, hunk.new_line), (8, 10));
+
+        // lines of a single `.`
+        let src = fs::read_to_string("test_data/ed/dot.diffs").unwrap();
+        let target = Path::new("test_data/ed/dot.before");
+        let com = Parser::parse_ed(&src, target).unwrap();
+        let after = fs::read_to_string("test_data/ed/dot.after").unwrap();
+        let before = fs::read_to_string(target).unwrap();
+        assert_eq!(com.diff[0].apply(&before).unwrap(), after);
+        assert!(Parser::parse_ed("2a\nx\n", target).is_err());
+    }
+
+    #[test]
+    fn test_parse_mbox() {
+        let src = fs::read_to_string("test_data/mbox/series.mbox").unwrap();
+        let patches = Parser::parse_mbox(&src).unwrap();
+        assert_eq!(patches.len(), 5);
+        let first = &patches[0];
+        assert_eq!(
+            first.commit.as_deref(),
+            Some("b9cc1b0e2ce76f3814aef4a37a1bf82eef5fefa5")
+        );
+        assert_eq!(first.author, "Ada Lovelace <ada@example.com>");
+        assert_eq!(first.date, "Sat, 2 Mar 2024 11:00:00 +0100");
+        assert_eq!(first.prefix.as_deref(), Some("PATCH 1/5"));
+        assert_eq!(first.subject, "Change two to a digit");
+        assert_eq!(
+            first.message,
+            "The digit reads better in the list.\n\n\
+             Signed-off-by: Ada Lovelace <ada@example.com>"
+        );
+        assert_eq!(first.diff.diff.len(), 2);
+        assert_eq!(first.signature.as_deref(), Some("2.39.5"));
+        // folded and encoded headers
+        assert!(
+            patches[1]
+                .subject
+                .ends_with("will have to fold over several header lines")
+        );
+        assert_eq!(patches[1].message, "");
+        assert_eq!(patches[2].author, "René Brûlé <ada@example.com>");
+        assert!(patches[2].subject.starts_with("Café Café"));
+        assert!(patches[2].subject.ends_with("Café au lait"));
+        assert_eq!(patches[3].author, "Doe, John <ada@example.com>");
+        assert_eq!(patches[3].diff.diff[0].status, FileStatus::Renamed);
+        assert!(patches[4].diff.diff[0].binary.is_some());
+        assert_eq!(patches[4].message, "And drop the exec bit.");
+
+        assert!(Parser::parse_mbox("From: Ada <ada@example.com>\n").is_err());
+
+        // a removed `- ` bullet without signature, and a message paragraph
+        // starting with `From `
+        let src = fs::read_to_string("test_data/mbox/bullet.mbox").unwrap();
+        let patches = Parser::parse_mbox(&src).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].signature, None);
+        assert_eq!(patches[0].diff.diff[0].hunk[0].change.len(), 5);
+        assert!(
+            patches[1].message.ends_with(
+                "From the docs: a list has as many bullets as needed."
+            )
+        );
+        assert_eq!(patches[1].signature, None);
+    }
+
+    #[test]
+    fn test_parse_combined() {
+        let src =
+            fs::read_to_string("test_data/combined/combined.diffs").unwrap();
+        let com = Parser::parse_combined(&src).unwrap();
+        let paths: Vec<_> =
+            com.diff.iter().map(|d| d.path.to_str().unwrap()).collect();
+        assert_eq!(
+            paths,
+            ["added.txt", "list.txt", "main.rs", "gone.txt", "run.sh"]
+        );
+        // empty ranges are numbered by the line before
+        let added = &com.diff[0];
+        assert_eq!(added.status, FileStatus::Added);
+        assert_eq!(added.hunk[0].old_range, [(0, 0), (0, 0)]);
+        assert_eq!((added.hunk[0].new_line, added.hunk[0].new_len), (1, 1));
+
+        let list = &com.diff[1].hunk[0];
+        assert_eq!(list.old_range, [(1, 18), (1, 18)]);
+        assert_eq!(list.new_len, 19);
+        assert_eq!(list.change[2].kind, [Change::Default, Change::Added]);
+        assert_eq!(list.change[2].content, b"THREE");
+        assert_eq!(list.change[3].kind, [Change::Added, Change::Default]);
+        let main = &com.diff[2].hunk[0];
+        assert_eq!(main.change[1].kind, [Change::Deleted, Change::Default]);
+        assert_eq!(main.change[3].kind, [Change::Added, Change::Added]);
+
+        let gone = &com.diff[3];
+        assert_eq!(gone.status, FileStatus::Deleted);
+        assert_eq!(gone.old_mode, [0o100644, 0o100644]);
+        assert_eq!(gone.hunk[0].change[0].kind, [Change::Deleted; 2]);
+        let run = &com.diff[4];
+        assert_eq!(run.old_mode, [0o100644, 0o100755]);
+        assert_eq!(run.new_mode, Some(0o100755));
+
+        let src = fs::read_to_string("test_data/combined/cc.diffs").unwrap();
+        let com = Parser::parse_combined(&src).unwrap();
+        assert_eq!(com.diff[1].hunk[0].old_range, [(1, 6), (1, 6)]);
+        // a hunk missing a line of the merge
+        let short =
+            src.replace("@@@ -1,6 -1,6 +1,7 @@@", "@@@ -1,6 -1,6 +1,8 @@@");
+        let err = Parser::parse_combined(&short).unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::HunkLengthMismatch));
+        assert!(Parser::parse_combined("@@@ -1 -1 +1 @@@\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let src = fs::read("test_data/lenient/ticket.diffs").unwrap();
+        let err = Parser::parse_git_udiff_bytes(&src).unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::InvalidLineStart));
+        let com = Parser::parse_git_udiff_with(&src, &options).unwrap();
+        assert_eq!(com.diff.len(), 2);
+        assert!(com.preamble.starts_with("Could you look at this one?"));
+        assert!(com.preamble.ends_with("<ada@example.com>\n\n"));
+        assert_eq!(com.trailer, "\nThanks,\nAda\n");
+        assert_eq!(com.to_git_udiff().as_bytes(), src);
+
+        // the commit header between the two commits is kept before the
+        // first diff of the second one
+        let src = fs::read_to_string("test_data/lenient/log.diffs").unwrap();
+        let com =
+            Parser::parse_git_udiff_with(src.as_bytes(), &options).unwrap();
+        assert_eq!(com.diff.len(), 4);
+        assert!(com.preamble.starts_with("commit 9f4d3618"));
+        assert_eq!(com.trailer, "");
+        let second = src.rfind("commit ").unwrap();
+        let diff = src[second..].find("diff --git").unwrap();
+        let first_diffs = src[..second].matches("diff --git").count();
+        assert_eq!(
+            com.interstitial,
+            [(first_diffs, src[second..second + diff].to_string())]
+        );
+        assert_eq!(com.to_git_udiff(), src);
+        assert_eq!(Writer::write_git_udiff_bytes(&com), src.as_bytes());
+
+        // a binary patch followed by the signature of an email
+        let src = fs::read("test_data/lenient/blob.patch").unwrap();
+        let com = Parser::parse_git_udiff_with(&src, &options).unwrap();
+        assert!(com.preamble.starts_with("From f451a885"));
+        assert!(matches!(com.diff[0].binary, Some(BinaryPatch::Git { .. })));
+        assert_eq!(com.diff[1].new_mode, Some(0o100644));
+        assert_eq!(com.trailer, "-- \n2.39.5\n\n");
+    }
+
+    #[test]
+    fn test_parse_quoted_paths() {
+        let src = fs::read_to_string("test_data/quoted.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let paths: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| (d.old_path.to_str().unwrap(), d.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                ("bin é.bin", "bin é.bin"),
+                ("café.txt", "café.txt"),
+                ("dir/my file.txt", "dir/my file.txt"),
+                ("plain", "new name é"),
+                ("q\" b/y", "q\" b/y"),
+                ("say \"hi\".txt", "say \"hi\".txt"),
+                ("tab\tname", "tab\tname"),
+                ("x\" b", "x\" b"),
+                ("q\" b/y", "q\" b/y"),
+                ("my file", "your file"),
+            ]
+        );
+        let quoted =
+            Parser::parse_quoted_path(r#""a/\a\\\101\303\251" rest"#, "");
+        assert_eq!(quoted.unwrap(), (PathBuf::from("a/\x07\\Aé"), " rest"));
+        for invalid in [r#""a/b"#, r#""a/\q""#, r#""a/\38""#] {
+            assert!(Parser::parse_quoted_path(invalid, "").is_err());
+        }
+        // a path which is not UTF-8, such as latin-1
+        #[cfg(unix)]
+        {
+            use {crate::writer::quote_path, std::os::unix::ffi::OsStrExt};
+            let (path, _) =
+                Parser::parse_quoted_path(r#""a/caf\351""#, "").unwrap();
+            assert_eq!(path.as_os_str().as_bytes(), b"a/caf\xe9");
+            assert_eq!(quote_path("", &path), r#""a/caf\351""#);
+        }
+        let src = "diff --git \"a/x\" \"b/y\" tail\n";
+        assert!(Parser::parse_git_udiff(src).is_err());
+    }
+
+    #[test]
+    fn test_is_epoch() {
+        assert!(Parser::is_epoch("1970-01-01 00:00:00.000000000 +0000"));
+        assert!(Parser::is_epoch("1970-01-01 01:00:00 +0100"));
+        assert!(Parser::is_epoch("1969-12-31 19:00:00.000000000 -0500"));
+        assert!(!Parser::is_epoch("1970-01-01 00:00:00.000000000 +0100"));
+        assert!(!Parser::is_epoch("1970-01-01 00:00:01.000000000 +0000"));
+        assert!(!Parser::is_epoch("2024-03-01 10:00:00.000000000 +0000"));
+        assert!(Parser::is_epoch("Thu Jan  1 00:00:00 1970"));
+        // `diff -c` in +0100 and -0500
+        assert!(Parser::is_epoch("Thu Jan  1 01:00:00 1970"));
+        assert!(Parser::is_epoch("Wed Dec 31 19:00:00 1969"));
+        assert!(!Parser::is_epoch("Thu Jan  1 01:00:01 1970"));
+        assert!(!Parser::is_epoch("Thu Jan  1 15:00:00 1970"));
+        assert!(!Parser::is_epoch("Fri Mar  1 10:00:00 2024"));
+    }
+
+    #[test]
+    fn test_parse_rename_copy() {
+        let src =
+            fs::read_to_string("test_data/composition/rename.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let files: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| {
+                (
+                    d.old_path.to_str().unwrap(),
+                    d.path.to_str().unwrap(),
+                    d.status,
+                    d.similarity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("big", "copy_dst", FileStatus::Copied, Some(94)),
+                ("big", "moved", FileStatus::Renamed, Some(90)),
+                ("mode_only", "renamed_plain", FileStatus::Renamed, Some(100)),
+            ]
+        );
+        assert_eq!(com.diff[1].new_mode, Some(0o100755));
+        assert!(com.diff[2].hunk.is_empty());
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let src = fs::read_to_string("test_data/binary/binary.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let kinds: Vec<_> = com
+            .diff
+            .iter()
+            .map(|d| match &d.binary {
+                Some(BinaryPatch::Git { forward, reverse }) => (
+                    forward.kind,
+                    forward.data.len(),
+                    reverse.as_ref().map(|r| (r.kind, r.data.len())),
+                ),
+                _ => panic!("expect a binary patch"),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (BinaryKind::Delta, 27, Some((BinaryKind::Delta, 21))),
+                (BinaryKind::Literal, 0, Some((BinaryKind::Literal, 7))),
+                (BinaryKind::Literal, 512, Some((BinaryKind::Literal, 0))),
+            ]
+        );
+        assert_eq!(com.diff[1].status, FileStatus::Deleted);
+
+        let src = fs::read_to_string("test_data/binary/stub.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        assert!(
+            com.diff
+                .iter()
+                .all(|d| matches!(d.binary, Some(BinaryPatch::Stub)))
+        );
+    }
 }
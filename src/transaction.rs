@@ -0,0 +1,274 @@
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// file writes committed all together.
+///
+/// every new content is first written to a temporary file next to its
+/// target, then moved over the target with a rename, so a target is either
+/// untouched or fully written. when a rename fails, the targets already
+/// replaced are restored to their original content the same way.
+/// removals are done along the renames, and restored the same way.
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    writes: Vec<Write>,
+}
+
+#[derive(Debug)]
+struct Write {
+    path: PathBuf,
+    /// `None` to remove the file
+    content: Option<Vec<u8>>,
+    /// unix mode to set, instead of keeping the original permissions
+    mode: Option<u32>,
+    /// file whose permissions are taken when the target does not exist
+    source: Option<PathBuf>,
+}
+
+/// a write ready to be renamed over its target
+#[derive(Debug)]
+struct Staged {
+    path: PathBuf,
+    /// `None` when the file is removed
+    temp: Option<PathBuf>,
+    /// content before the transaction, `None` when the file did not exist
+    original: Option<Vec<u8>>,
+    permissions: Option<fs::Permissions>,
+}
+
+impl Transaction {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// content `path` has once the queued writes are committed, read from
+    /// the disk when no write is queued for it
+    pub(crate) fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.writes.iter().find(|w| w.path == path) {
+            Some(Write {
+                content: Some(content),
+                ..
+            }) => Ok(content.clone()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is removed", path.display()),
+            )),
+            None => fs::read(path),
+        }
+    }
+
+    /// whether `path` exists once the queued writes are committed
+    pub(crate) fn exists(&self, path: &Path) -> bool {
+        match self.writes.iter().find(|w| w.path == path) {
+            Some(write) => write.content.is_some(),
+            None => path.exists(),
+        }
+    }
+
+    /// queue a write, replacing the one queued for the same path so each
+    /// path is staged once. a mode or source already queued is kept
+    fn push(&mut self, write: Write) {
+        match self.writes.iter_mut().find(|w| w.path == write.path) {
+            Some(queued) => {
+                queued.content = write.content;
+                queued.mode = write.mode.or(queued.mode);
+                queued.source = write.source.or(queued.source.take());
+            }
+            None => self.writes.push(write),
+        }
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+    ) {
+        self.write_mode(path, content, None);
+    }
+
+    /// write `content`, setting the permission bits of `mode` if any
+    pub(crate) fn write_mode(
+        &mut self,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+        mode: Option<u32>,
+    ) {
+        self.push(Write {
+            path: path.into(),
+            content: Some(content.into()),
+            mode,
+            source: None,
+        });
+    }
+
+    /// write `content` as a copy of `source`, taking its permissions
+    /// unless `mode` is set
+    pub(crate) fn write_copy(
+        &mut self,
+        source: impl Into<PathBuf>,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+        mode: Option<u32>,
+    ) {
+        self.push(Write {
+            path: path.into(),
+            content: Some(content.into()),
+            mode,
+            source: Some(source.into()),
+        });
+    }
+
+    pub(crate) fn remove(&mut self, path: impl Into<PathBuf>) {
+        self.push(Write {
+            path: path.into(),
+            content: None,
+            mode: None,
+            source: None,
+        });
+    }
+
+    pub(crate) fn commit(self) -> io::Result<()> {
+        let mut staged = Vec::with_capacity(self.writes.len());
+        for write in &self.writes {
+            match Self::stage(write) {
+                Ok(Some(s)) => staged.push(s),
+                Ok(None) => {}
+                Err(e) => {
+                    Self::cleanup(&staged);
+                    return Err(e);
+                }
+            }
+        }
+
+        for (idx, s) in staged.iter().enumerate() {
+            let result = match &s.temp {
+                Some(temp) => fs::rename(temp, &s.path),
+                None => fs::remove_file(&s.path),
+            };
+            if let Err(e) = result {
+                Self::rollback(&staged[..idx]);
+                Self::cleanup(&staged[idx..]);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// `None` for a removal of a file which does not exist, such as a file
+    /// created then removed by the same transaction
+    fn stage(write: &Write) -> io::Result<Option<Staged>> {
+        let (original, permissions) = match fs::read(&write.path) {
+            Ok(original) => {
+                let permissions = fs::metadata(&write.path)?.permissions();
+                (Some(original), Some(permissions))
+            }
+            Err(e)
+                if e.kind() == io::ErrorKind::NotFound
+                    && write.content.is_some() =>
+            {
+                let permissions = match &write.source {
+                    Some(source) => Some(fs::metadata(source)?.permissions()),
+                    None => None,
+                };
+                (None, permissions)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let temp = match &write.content {
+            Some(content) => {
+                if let Some(parent) = write.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let temp = Self::temp_path(&write.path);
+                let permissions = write
+                    .mode
+                    .and_then(Self::mode_permissions)
+                    .or_else(|| permissions.clone());
+                Self::write_temp(&temp, content, permissions.as_ref())?;
+                Some(temp)
+            }
+            None => None,
+        };
+        Ok(Some(Staged {
+            path: write.path.clone(),
+            temp,
+            original,
+            permissions,
+        }))
+    }
+
+    fn write_temp(
+        temp: &Path,
+        content: &[u8],
+        permissions: Option<&fs::Permissions>,
+    ) -> io::Result<()> {
+        // the content is synced before the rename, so a crash cannot
+        // leave a truncated target
+        let write = || {
+            let mut file = fs::File::create(temp)?;
+            file.write_all(content)?;
+            file.sync_all()
+        };
+        let result = write().and_then(|_| match permissions {
+            Some(p) => fs::set_permissions(temp, p.clone()),
+            None => Ok(()),
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(temp);
+        }
+        result
+    }
+
+    /// restore the original content of committed writes, best effort
+    fn rollback(committed: &[Staged]) {
+        for s in committed.iter().rev() {
+            match &s.original {
+                Some(original) => {
+                    let temp = Self::temp_path(&s.path);
+                    if Self::write_temp(&temp, original, s.permissions.as_ref())
+                        .is_ok()
+                    {
+                        let _ = fs::rename(&temp, &s.path);
+                    }
+                }
+                None => {
+                    let _ = fs::remove_file(&s.path);
+                }
+            }
+        }
+    }
+
+    fn cleanup(staged: &[Staged]) {
+        for temp in staged.iter().filter_map(|s| s.temp.as_ref()) {
+            let _ = fs::remove_file(temp);
+        }
+    }
+
+    /// permissions of a git mode. only regular files have some, and
+    /// they are only set on unix
+    #[cfg(unix)]
+    fn mode_permissions(mode: u32) -> Option<fs::Permissions> {
+        use std::os::unix::fs::PermissionsExt;
+        (mode & 0o170000 == 0o100000)
+            .then(|| fs::Permissions::from_mode(mode & 0o777))
+    }
+
+    #[cfg(not(unix))]
+    fn mode_permissions(_mode: u32) -> Option<fs::Permissions> {
+        None
+    }
+
+    /// `.<file_name>.<pid>.tmp` next to `path`
+    fn temp_path(path: &Path) -> PathBuf {
+        let mut name = std::ffi::OsString::from(".");
+        if let Some(file_name) = path.file_name() {
+            name.push(file_name);
+        }
+        name.push(format!(".{}.tmp", process::id()));
+        path.with_file_name(name)
+    }
+}
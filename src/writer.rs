@@ -0,0 +1,1219 @@
+use {
+    crate::{binary, diff::*, parser::Parser},
+    std::{fmt, path::Path},
+};
+
+pub struct Writer {}
+
+impl Writer {
+    pub fn write_git_udiff(comp: &DiffComposition) -> String {
+        let mut out = comp.preamble.clone();
+        for (i, diff) in comp.diff.iter().enumerate() {
+            out.extend(Self::interstitial(comp, i));
+            out.push_str(&diff.to_string());
+        }
+        out.push_str(&comp.trailer);
+        out
+    }
+
+    /// write the diffs keeping the bytes of the changed lines, which may
+    /// not be UTF-8
+    pub fn write_git_udiff_bytes(comp: &DiffComposition) -> Vec<u8> {
+        let mut out = comp.preamble.as_bytes().to_vec();
+        for (i, diff) in comp.diff.iter().enumerate() {
+            for text in Self::interstitial(comp, i) {
+                out.extend_from_slice(text.as_bytes());
+            }
+            out.extend(diff.to_bytes());
+        }
+        out.extend_from_slice(comp.trailer.as_bytes());
+        out
+    }
+
+    /// text kept by the lenient parser before the diff at `index`
+    fn interstitial(
+        comp: &DiffComposition,
+        index: usize,
+    ) -> impl Iterator<Item = &str> {
+        comp.interstitial
+            .iter()
+            .filter(move |(at, _)| *at == index)
+            .map(|(_, text)| text.as_str())
+    }
+
+    /// write the diffs in the unified format of `diff -u`, without the
+    /// git headers. content which is not UTF-8 is written lossily
+    pub fn write_unified(comp: &DiffComposition) -> String {
+        String::from_utf8_lossy(&Self::write_unified_bytes(comp)).into_owned()
+    }
+
+    pub fn write_unified_bytes(comp: &DiffComposition) -> Vec<u8> {
+        let mut out = Vec::new();
+        for diff in &comp.diff {
+            let headers = PlainHeaders(diff, DiffFormat::Unified);
+            out.extend(headers.to_string().into_bytes());
+            for hunk in &diff.hunk {
+                out.extend(hunk.to_bytes());
+            }
+        }
+        out
+    }
+
+    /// write the diffs in the context format of `diff -c`. content which
+    /// is not UTF-8 is written lossily
+    pub fn write_context(comp: &DiffComposition) -> String {
+        String::from_utf8_lossy(&Self::write_context_bytes(comp)).into_owned()
+    }
+
+    pub fn write_context_bytes(comp: &DiffComposition) -> Vec<u8> {
+        let mut out = Vec::new();
+        for diff in &comp.diff {
+            let headers = PlainHeaders(diff, DiffFormat::Context);
+            out.extend(headers.to_string().into_bytes());
+            for hunk in &diff.hunk {
+                out.extend(Self::context_hunk_bytes(hunk));
+            }
+        }
+        out
+    }
+
+    /// write the diffs in the default format of `diff`, leaving out the
+    /// context of the hunks. content which is not UTF-8 is written lossily
+    pub fn write_normal(comp: &DiffComposition) -> String {
+        String::from_utf8_lossy(&Self::write_normal_bytes(comp)).into_owned()
+    }
+
+    pub fn write_normal_bytes(comp: &DiffComposition) -> Vec<u8> {
+        let mut out = Vec::new();
+        for diff in &comp.diff {
+            let headers = PlainHeaders(diff, DiffFormat::Normal);
+            out.extend(headers.to_string().into_bytes());
+            for hunk in &diff.hunk {
+                for (old, removed, new, added) in Self::edits(hunk) {
+                    let command = match (removed.len(), added.len()) {
+                        (0, len) => format!("{}a{}", old - 1, range(new, len)),
+                        (len, 0) => format!("{}d{}", range(old, len), new - 1),
+                        (len, new_len) => {
+                            format!(
+                                "{}c{}",
+                                range(old, len),
+                                range(new, new_len)
+                            )
+                        }
+                    };
+                    out.extend(command.into_bytes());
+                    out.push(b'\n');
+                    let lines =
+                        |out: &mut Vec<u8>,
+                         sign: &[u8],
+                         lines: &[&LineChange]| {
+                            for change in lines {
+                                out.extend_from_slice(sign);
+                                out.extend_from_slice(&change.content);
+                                out.push(b'\n');
+                                if change.no_newline {
+                                    out.extend_from_slice(
+                                        DIFF_SIGN_NO_NEWLINE.as_bytes(),
+                                    );
+                                    out.push(b'\n');
+                                }
+                            }
+                        };
+                    lines(&mut out, b"< ", &removed);
+                    if !removed.is_empty() && !added.is_empty() {
+                        out.extend_from_slice(b"---\n");
+                    }
+                    lines(&mut out, b"> ", &added);
+                }
+            }
+        }
+        out
+    }
+
+    /// write the diffs as the ed scripts of `diff -e`, leaving out the
+    /// context of the hunks. an ed script cannot tell that a file does not
+    /// end with a newline. content which is not UTF-8 is written lossily
+    pub fn write_ed(comp: &DiffComposition) -> String {
+        String::from_utf8_lossy(&Self::write_ed_bytes(comp)).into_owned()
+    }
+
+    pub fn write_ed_bytes(comp: &DiffComposition) -> Vec<u8> {
+        let mut out = Vec::new();
+        for diff in &comp.diff {
+            let headers = PlainHeaders(diff, DiffFormat::Ed);
+            out.extend(headers.to_string().into_bytes());
+            // the edits go from the end, so that the line numbers of the
+            // next ones still hold
+            let edits: Vec<_> =
+                diff.hunk.iter().flat_map(Self::edits).collect();
+            for (old, removed, _, added) in edits.into_iter().rev() {
+                let command = match removed.len() {
+                    0 => format!("{}a", old - 1),
+                    len if added.is_empty() => format!("{}d", range(old, len)),
+                    len => format!("{}c", range(old, len)),
+                };
+                out.extend(command.into_bytes());
+                out.push(b'\n');
+                if added.is_empty() {
+                    continue;
+                }
+                // a line of a single `.` would end the lines, it is written
+                // `..` and fixed once they end
+                let mut ended = false;
+                for (i, change) in added.iter().enumerate() {
+                    match change.content.as_slice() {
+                        b"." => {
+                            out.extend_from_slice(b"..\n.\ns/.//\n");
+                            ended = i + 1 == added.len();
+                            if !ended {
+                                out.extend_from_slice(b"a\n");
+                            }
+                        }
+                        content => {
+                            out.extend_from_slice(content);
+                            out.push(b'\n');
+                        }
+                    }
+                }
+                if !ended {
+                    out.extend_from_slice(b".\n");
+                }
+            }
+        }
+        out
+    }
+
+    /// runs of changes of a hunk, with the first old line of each, the
+    /// lines it removes, the first new line and the lines it adds
+    fn edits(
+        hunk: &DiffHunk,
+    ) -> Vec<(usize, Vec<&LineChange>, usize, Vec<&LineChange>)> {
+        // an empty range is numbered by the line before it
+        let first = |line: usize, len: usize| match len {
+            0 => line + 1,
+            _ => line,
+        };
+        let mut old = first(hunk.old_line, hunk.old_len);
+        let mut new = first(hunk.new_line, hunk.new_len);
+        let mut edits = Vec::new();
+        for run in hunk.change.chunk_by(|a, b| {
+            (a.kind == Change::Default) == (b.kind == Change::Default)
+        }) {
+            if run[0].kind == Change::Default {
+                old += run.len();
+                new += run.len();
+                continue;
+            }
+            let lines = |kind| {
+                run.iter().filter(|c| c.kind == kind).collect::<Vec<_>>()
+            };
+            let (removed, added) =
+                (lines(Change::Deleted), lines(Change::Added));
+            let (next_old, next_new) = (old + removed.len(), new + added.len());
+            edits.push((old, removed, new, added));
+            (old, new) = (next_old, next_new);
+        }
+        edits
+    }
+
+    /// write the patches as the emails of `git format-patch --stdout`,
+    /// with the diffs in git format after their diffstat
+    pub fn write_mbox(patches: &[Patch]) -> String {
+        String::from_utf8_lossy(&Self::write_mbox_bytes(patches)).into_owned()
+    }
+
+    pub fn write_mbox_bytes(patches: &[Patch]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, patch) in patches.iter().enumerate() {
+            if i > 0 {
+                out.push(b'\n');
+            }
+            out.extend(Self::mail_headers(patch).into_bytes());
+            out.extend(Self::write_git_udiff_bytes(&patch.diff));
+            if let Some(signature) = &patch.signature {
+                let signature = format!("{MAIL_SIGNATURE}\n{signature}\n\n");
+                out.extend(signature.into_bytes());
+            }
+        }
+        out
+    }
+
+    /// every line of an email before the diff
+    fn mail_headers(patch: &Patch) -> String {
+        let zero = "0".repeat(40);
+        let commit = patch.commit.as_deref().unwrap_or(&zero);
+        let mut out = format!("From {commit} {MAIL_FROM_DATE}\n");
+        out.push_str("From: ");
+        let author = match patch.author.rfind(" <") {
+            Some(at) => patch.author.split_at(at),
+            None => (patch.author.as_str(), ""),
+        };
+        match author.0 {
+            name if needs_rfc2047(name) => {
+                out.push_str(&rfc2047(name, 6, true))
+            }
+            name if name.contains(|c| "()<>@,;:\\\".[]".contains(c)) => {
+                let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+                out.push_str(&format!("\"{name}\""));
+            }
+            name => out.push_str(name),
+        }
+        out.push_str(author.1);
+        out.push_str(&format!("\nDate: {}\n", patch.date));
+
+        let mut subject = String::from("Subject: ");
+        if let Some(prefix) = &patch.prefix {
+            subject.push_str(&format!("[{prefix}] "));
+        }
+        if needs_rfc2047(&patch.subject) {
+            subject.push_str(&rfc2047(&patch.subject, subject.len(), false));
+        } else {
+            // lines are folded after 78 characters
+            let mut len = subject.len();
+            for (i, word) in patch.subject.split(' ').enumerate() {
+                let space = usize::from(i > 0);
+                if len + space + word.len() > 78 {
+                    subject.push_str("\n ");
+                    len = 1;
+                } else if i > 0 {
+                    subject.push(' ');
+                    len += 1;
+                }
+                subject.push_str(word);
+                len += word.len();
+            }
+        }
+        out.push_str(&subject);
+        out.push('\n');
+        if !patch.subject.is_ascii() || !patch.message.is_ascii() {
+            out.push_str("MIME-Version: 1.0\n");
+            out.push_str("Content-Type: text/plain; charset=UTF-8\n");
+            out.push_str("Content-Transfer-Encoding: 8bit\n");
+        }
+        out.push('\n');
+        if !patch.message.is_empty() {
+            out.push_str(&patch.message);
+            out.push('\n');
+        }
+        out.push_str(DIFF_SIGN_HEADER_ORIGIN);
+        out.push('\n');
+        out.push_str(&diffstat(&patch.diff));
+        out.push('\n');
+        out
+    }
+
+    /// a hunk written as its old range then its new range. a run of changes
+    /// both removing and adding lines is marked `!` on both sides
+    fn context_hunk_bytes(hunk: &DiffHunk) -> Vec<u8> {
+        let mut markers = Vec::with_capacity(hunk.change.len());
+        for run in hunk.change.chunk_by(|a, b| {
+            (a.kind == Change::Default) == (b.kind == Change::Default)
+        }) {
+            let changed = run.iter().any(|c| c.kind == Change::Added)
+                && run.iter().any(|c| c.kind == Change::Deleted);
+            markers.extend(run.iter().map(|c| match c.kind {
+                Change::Default => DIFF_SIGN_LINE_DEFAULT,
+                _ if changed => DIFF_SIGN_LINE_CHANGED,
+                Change::Added => DIFF_SIGN_LINE_ADDED,
+                Change::Deleted => DIFF_SIGN_LINE_DELETED,
+            }));
+        }
+        // the length of a range is not written, but its last line
+        let range = |line: usize, len: usize| match len {
+            0 | 1 => format!("{line}"),
+            _ => format!("{line},{}", line + len - 1),
+        };
+
+        let mut out = DIFF_SIGN_CONTEXT_SEPARATOR.as_bytes().to_vec();
+        if let Some(heading) = &hunk.heading {
+            out.push(b' ');
+            out.extend_from_slice(heading.as_bytes());
+        }
+        out.push(b'\n');
+        let sides = [
+            (
+                DIFF_SIGN_CONTEXT_ORIGIN,
+                "****",
+                Change::Added,
+                hunk.old_line,
+                hunk.old_len,
+            ),
+            (
+                DIFF_SIGN_HEADER_ORIGIN,
+                "----",
+                Change::Deleted,
+                hunk.new_line,
+                hunk.new_len,
+            ),
+        ];
+        for (sign, end, other, line, len) in sides {
+            let range = range(line, len);
+            out.extend(format!("{sign} {range} {end}\n").into_bytes());
+            let lines = hunk
+                .change
+                .iter()
+                .zip(&markers)
+                .filter(|(c, _)| c.kind != other);
+            // a side with only context is left to the other side
+            if lines.clone().all(|(c, _)| c.kind == Change::Default) {
+                continue;
+            }
+            for (change, marker) in lines {
+                out.extend_from_slice(marker.as_bytes());
+                out.push(b' ');
+                out.extend_from_slice(&change.content);
+                out.push(b'\n');
+                if change.no_newline {
+                    out.extend_from_slice(DIFF_SIGN_NO_NEWLINE.as_bytes());
+                    out.push(b'\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for DiffComposition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            DiffFormat::GitUdiff => f.write_str(&Writer::write_git_udiff(self)),
+            DiffFormat::Unified => f.write_str(&Writer::write_unified(self)),
+            DiffFormat::Context => f.write_str(&Writer::write_context(self)),
+            DiffFormat::Normal => f.write_str(&Writer::write_normal(self)),
+            DiffFormat::Ed => f.write_str(&Writer::write_ed(self)),
+        }
+    }
+}
+
+impl DiffComposition {
+    pub fn to_git_udiff(&self) -> String {
+        Writer::write_git_udiff(self)
+    }
+}
+
+impl Diff {
+    /// the diff as written in git format, keeping the bytes of its lines
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Headers(self).to_string().into_bytes();
+        for hunk in &self.hunk {
+            out.extend(hunk.to_bytes());
+        }
+        out
+    }
+}
+
+/// content which is not UTF-8 is written lossily, see `Diff::to_bytes`
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Headers(self))?;
+        for hunk in &self.hunk {
+            write!(f, "{hunk}")?;
+        }
+        Ok(())
+    }
+}
+
+/// every line of a diff before its hunks
+struct Headers<'a>(&'a Diff);
+
+impl fmt::Display for Headers<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Headers(diff) = self;
+        let old_path = quote_path("", &diff.old_path);
+        let path = quote_path("", &diff.path);
+        let (old_name, name) = (
+            quote_path("a/", &diff.old_path),
+            quote_path("b/", &diff.path),
+        );
+        match &diff.command {
+            Some(command) => writeln!(f, "{command}")?,
+            None => writeln!(f, "diff --git {old_name} {name}")?,
+        }
+        match (diff.status, diff.old_mode, diff.new_mode) {
+            (FileStatus::Added, _, Some(mode)) => {
+                writeln!(f, "new file mode {mode:06o}")?
+            }
+            (FileStatus::Deleted, Some(mode), _) => {
+                writeln!(f, "deleted file mode {mode:06o}")?
+            }
+            (FileStatus::Added | FileStatus::Deleted, _, _) => {}
+            (_, Some(old), Some(new)) => {
+                writeln!(f, "old mode {old:06o}")?;
+                writeln!(f, "new mode {new:06o}")?;
+            }
+            _ => {}
+        }
+        if let Some(similarity) = diff.similarity {
+            writeln!(f, "similarity index {similarity}%")?;
+        }
+        if let Some(dissimilarity) = diff.dissimilarity {
+            writeln!(f, "dissimilarity index {dissimilarity}%")?;
+        }
+        match diff.status {
+            FileStatus::Renamed => {
+                writeln!(f, "rename from {old_path}")?;
+                writeln!(f, "rename to {path}")?;
+            }
+            FileStatus::Copied => {
+                writeln!(f, "copy from {old_path}")?;
+                writeln!(f, "copy to {path}")?;
+            }
+            _ => {}
+        }
+        if let Some(index) = &diff.index {
+            writeln!(f, "index {index}")?;
+        }
+        match &diff.binary {
+            Some(BinaryPatch::Stub) => {
+                let old = match diff.status {
+                    FileStatus::Added => DEV_NULL,
+                    _ => &old_name,
+                };
+                let new = match diff.status {
+                    FileStatus::Deleted => DEV_NULL,
+                    _ => &name,
+                };
+                return writeln!(
+                    f,
+                    "{DIFF_SIGN_BINARY_STUB}{old} and {new} differ"
+                );
+            }
+            Some(BinaryPatch::Git { forward, reverse }) => {
+                writeln!(f, "{DIFF_SIGN_BINARY}")?;
+                write!(f, "{forward}")?;
+                if let Some(reverse) = reverse {
+                    write!(f, "{reverse}")?;
+                }
+                return Ok(());
+            }
+            None => {}
+        }
+        // git only writes the paths when there is content to change
+        if diff.hunk.is_empty() {
+            return Ok(());
+        }
+        // a path with a space ends with a tab
+        let tab = |path: &Path| match path.to_string_lossy().contains(' ') {
+            true => "\t",
+            false => "",
+        };
+        match diff.status {
+            FileStatus::Added => {
+                writeln!(f, "{DIFF_SIGN_HEADER_ORIGIN} {DEV_NULL}")?
+            }
+            _ => writeln!(
+                f,
+                "{DIFF_SIGN_HEADER_ORIGIN} {old_name}{}",
+                tab(&diff.old_path)
+            )?,
+        }
+        match diff.status {
+            FileStatus::Deleted => {
+                writeln!(f, "{DIFF_SIGN_HEADER_NEW} {DEV_NULL}")?
+            }
+            _ => {
+                writeln!(f, "{DIFF_SIGN_HEADER_NEW} {name}{}", tab(&diff.path))?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `prefix` and `path` as git writes them in its headers, in double quotes
+/// with C escapes when the path has control characters, quotes,
+/// backslashes or bytes out of ASCII
+pub(crate) fn quote_path(prefix: &str, path: &Path) -> String {
+    // the bytes of the path as they are on unix, which may not be UTF-8
+    let mut name = prefix.as_bytes().to_vec();
+    name.extend_from_slice(path.as_os_str().as_encoded_bytes());
+    let plain = |b: &u8| (0x20..0x7f).contains(b) && !b"\"\\".contains(b);
+    if name.iter().all(plain) {
+        return String::from_utf8_lossy(&name).into_owned();
+    }
+    let mut out = String::from('"');
+    for b in name {
+        match b {
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            0x0b => out.push_str("\\v"),
+            0x0c => out.push_str("\\f"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b if plain(&b) => out.push(b as char),
+            b => out.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// whether an email header has to be written as `=?UTF-8?q?...?=`
+fn needs_rfc2047(text: &str) -> bool {
+    !text.is_ascii() || text.contains('\n') || text.contains("=?")
+}
+
+/// `text` in the Q encoding of RFC 2047, as git writes it. the encoded
+/// words are folded to 76 characters, the first line starting after `len`
+/// characters. the name of an address has more characters to encode
+fn rfc2047(text: &str, len: usize, address: bool) -> String {
+    let special = |b: u8| match b {
+        b' ' | b'=' | b'?' | b'_' => true,
+        b if !b.is_ascii_graphic() => true,
+        b => address && !(b.is_ascii_alphanumeric() || b"!*+-/".contains(&b)),
+    };
+    let mut out = String::from("=?UTF-8?q?");
+    let mut len = len + out.len();
+    for c in text.chars() {
+        let mut buf = [0; 4];
+        let bytes = c.encode_utf8(&mut buf).as_bytes();
+        // a character is not split across encoded words
+        let encode = bytes.len() > 1 || special(bytes[0]);
+        let encoded_len = if encode { 3 * bytes.len() } else { 1 };
+        if len + encoded_len + 2 > 76 {
+            out.push_str("?=\n =?UTF-8?q?");
+            len = 11;
+        }
+        for b in bytes {
+            match encode {
+                true => out.push_str(&format!("={b:02X}")),
+                false => out.push(*b as char),
+            }
+        }
+        len += encoded_len;
+    }
+    out.push_str("?=");
+    out
+}
+
+/// number of digits of `n`
+fn decimal_width(n: usize) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+/// `old => new` with the common directories out of braces, as
+/// `src/{a => b}/lib.rs`
+fn rename_name(old: &Path, new: &Path) -> String {
+    let old = old.to_string_lossy();
+    let new = new.to_string_lossy();
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // the suffix may start at the slash ending the prefix
+    let at = |s: &[u8], i: isize| s.get(i as usize).copied().unwrap_or(0);
+    let floor = prefix as isize - isize::from(prefix > 0);
+    let (mut i, mut j) = (a.len() as isize, b.len() as isize);
+    let mut suffix = 0;
+    while floor <= i && floor <= j && at(a, i) == at(b, j) {
+        if at(a, i) == b'/' {
+            suffix = a.len() - i as usize;
+        }
+        i -= 1;
+        j -= 1;
+    }
+    let a_mid = a.len().saturating_sub(prefix + suffix);
+    let b_mid = b.len().saturating_sub(prefix + suffix);
+    let mut out = Vec::with_capacity(a.len() + b.len() + 6);
+    let braces = prefix + suffix > 0;
+    if braces {
+        out.extend_from_slice(&a[..prefix]);
+        out.push(b'{');
+    }
+    out.extend_from_slice(&a[prefix..prefix + a_mid]);
+    out.extend_from_slice(b" => ");
+    out.extend_from_slice(&b[prefix..prefix + b_mid]);
+    if braces {
+        out.push(b'}');
+        out.extend_from_slice(&a[a.len() - suffix..]);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// diffstat of `git format-patch`, scaled to 72 columns, with its summary
+fn diffstat(comp: &DiffComposition) -> String {
+    const WIDTH: usize = 72;
+    // name, added and removed lines, or sizes of a binary file
+    let stats: Vec<(String, usize, usize, bool)> = comp
+        .diff
+        .iter()
+        .map(|diff| {
+            let name = match diff.old_path == diff.path {
+                true => quote_path("", &diff.path),
+                false => rename_name(&diff.old_path, &diff.path),
+            };
+            if let Some(binary) = &diff.binary {
+                let (old, new) = binary_sizes(binary);
+                return (name, new, old, true);
+            }
+            let count = |kind: Change| {
+                diff.hunk
+                    .iter()
+                    .flat_map(|hunk| &hunk.change)
+                    .filter(|c| c.kind == kind)
+                    .count()
+            };
+            (name, count(Change::Added), count(Change::Deleted), false)
+        })
+        .collect();
+
+    let max_len = stats.iter().map(|s| s.0.chars().count()).max();
+    let max_len = max_len.unwrap_or_default();
+    let changes = stats.iter().filter(|s| !s.3).map(|s| s.1 + s.2);
+    let max_change = changes.max().unwrap_or_default();
+    let binary = stats.iter().filter(|s| s.3);
+    let bin_width = binary
+        .clone()
+        .map(|s| 14 + decimal_width(s.1) + decimal_width(s.2))
+        .max()
+        .unwrap_or_default();
+    let mut number_width = decimal_width(max_change);
+    if binary.count() > 0 {
+        number_width = number_width.max(3);
+    }
+    let width = WIDTH.max(16 + 6 + number_width);
+    let mut graph_width = match max_change + 4 > bin_width {
+        true => max_change,
+        false => bin_width - 4,
+    };
+    let mut name_width = max_len;
+    // the graph gets 3/8 of the width at most when it does not fit
+    if name_width + number_width + 6 + graph_width > width {
+        let limit = (width * 3 / 8) as isize - number_width as isize - 6;
+        if graph_width as isize > limit {
+            graph_width = limit.max(6) as usize;
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let mut out = String::new();
+    for (name, added, deleted, binary) in &stats {
+        let mut name = name.as_str();
+        let mut prefix = "";
+        let mut len = name_width;
+        let name_len = name.chars().count();
+        if name_width < name_len {
+            prefix = "...";
+            len = len.saturating_sub(3);
+            let skip = name.char_indices().nth(name_len - len);
+            name = &name[skip.map_or(name.len(), |(at, _)| at)..];
+            if let Some(at) = name.find('/') {
+                name = &name[at..];
+            }
+        }
+        let padding = len.saturating_sub(name.chars().count());
+        out.push_str(&format!(" {prefix}{name}{:padding$} | ", ""));
+        if *binary {
+            out.push_str(&format!("{:>number_width$}", "Bin"));
+            if *added > 0 || *deleted > 0 {
+                out.push_str(&format!(" {deleted} -> {added} bytes"));
+            }
+            out.push('\n');
+            continue;
+        }
+        let (mut add, mut del) = (*added, *deleted);
+        if graph_width <= max_change {
+            // at least one sign for a change
+            let scale = |it: usize| match it {
+                0 => 0,
+                it => 1 + it * (graph_width - 1) / max_change,
+            };
+            let mut total = scale(add + del);
+            if total < 2 && add > 0 && del > 0 {
+                total = 2;
+            }
+            if add < del {
+                add = scale(add);
+                del = total - add;
+            } else {
+                del = scale(del);
+                add = total - del;
+            }
+        }
+        let total = added + deleted;
+        out.push_str(&format!("{total:>number_width$}"));
+        if total > 0 {
+            out.push(' ');
+        }
+        out.push_str(&"+".repeat(add));
+        out.push_str(&"-".repeat(del));
+        out.push('\n');
+    }
+
+    let plural = |n: usize, one: &str, many: &str| match n {
+        1 => format!("{n} {one}"),
+        _ => format!("{n} {many}"),
+    };
+    let text = stats.iter().filter(|s| !s.3);
+    let insertions: usize = text.clone().map(|s| s.1).sum();
+    let deletions: usize = text.map(|s| s.2).sum();
+    out.push(' ');
+    out.push_str(&plural(stats.len(), "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        out.push_str(", ");
+        out.push_str(&plural(insertions, "insertion(+)", "insertions(+)"));
+    }
+    if deletions > 0 || insertions == 0 {
+        out.push_str(", ");
+        out.push_str(&plural(deletions, "deletion(-)", "deletions(-)"));
+    }
+    out.push('\n');
+
+    for diff in &comp.diff {
+        let path = quote_path("", &diff.path);
+        let mode_change = match (diff.old_mode, diff.new_mode) {
+            (Some(old), Some(new)) if old != new => {
+                Some(format!(" mode change {old:06o} => {new:06o}"))
+            }
+            _ => None,
+        };
+        match diff.status {
+            FileStatus::Added | FileStatus::Deleted => {
+                let (action, mode) = match diff.status {
+                    FileStatus::Added => ("create", diff.new_mode),
+                    _ => ("delete", diff.old_mode),
+                };
+                match mode {
+                    Some(mode) => out.push_str(&format!(
+                        " {action} mode {mode:06o} {path}\n"
+                    )),
+                    None => out.push_str(&format!(" {action} {path}\n")),
+                }
+            }
+            FileStatus::Renamed | FileStatus::Copied => {
+                let action = match diff.status {
+                    FileStatus::Renamed => "rename",
+                    _ => "copy",
+                };
+                let name = rename_name(&diff.old_path, &diff.path);
+                let similarity = diff.similarity.unwrap_or_default();
+                out.push_str(&format!(" {action} {name} ({similarity}%)\n"));
+                if let Some(mode_change) = mode_change {
+                    out.push_str(&format!("{mode_change}\n"));
+                }
+            }
+            _ => {
+                if let Some(dissimilarity) = diff.dissimilarity {
+                    out.push_str(&format!(
+                        " rewrite {path} ({dissimilarity}%)\n"
+                    ));
+                }
+                if let Some(mode_change) = mode_change {
+                    out.push_str(&format!("{mode_change} {path}\n"));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// sizes of a binary file before and after the diff, 0 when unknown
+fn binary_sizes(binary: &BinaryPatch) -> (usize, usize) {
+    let BinaryPatch::Git { forward, reverse } = binary else {
+        return (0, 0);
+    };
+    let size = |hunk: &BinaryHunk| match hunk.kind {
+        BinaryKind::Literal => Some((None, hunk.data.len())),
+        BinaryKind::Delta => binary::delta_sizes(&hunk.data)
+            .map(|(source, result)| (Some(source), result)),
+    };
+    let (source, new) = size(forward).unwrap_or_default();
+    let old = match reverse.as_ref().and_then(size) {
+        Some((_, old)) => old,
+        None => source.unwrap_or_default(),
+    };
+    (old, new)
+}
+
+/// range of a normal diff or an ed script, as its first and last lines
+fn range(first: usize, len: usize) -> String {
+    match len {
+        0 | 1 => format!("{first}"),
+        _ => format!("{first},{}", first + len - 1),
+    }
+}
+
+/// every line of a diff not made by git before its hunks
+struct PlainHeaders<'a>(&'a Diff, DiffFormat);
+
+impl PlainHeaders<'_> {
+    /// path and timestamp of a side of the diff. a missing file is
+    /// `/dev/null`, unless dated at the epoch as `diff -N` writes it
+    fn label(path: &Path, timestamp: &Option<String>, missing: bool) -> String {
+        let epoch = timestamp.as_deref().is_some_and(Parser::is_epoch);
+        let path = match missing && !epoch {
+            true => DEV_NULL.to_string(),
+            false => path.display().to_string(),
+        };
+        match timestamp {
+            Some(timestamp) => format!("{path}\t{timestamp}"),
+            None => path,
+        }
+    }
+}
+
+impl fmt::Display for PlainHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PlainHeaders(diff, format) = self;
+        let edits = matches!(format, DiffFormat::Normal | DiffFormat::Ed);
+        match &diff.command {
+            // the git command does not belong to this format
+            Some(command) if !command.starts_with("diff --git ") => {
+                writeln!(f, "{command}")?
+            }
+            // the command holds the paths of a diff without labels
+            _ if edits
+                && diff.binary.is_none()
+                && !diff.hunk.is_empty()
+                && !diff.path.as_os_str().is_empty() =>
+            {
+                let option = match format {
+                    DiffFormat::Ed => " -e",
+                    _ => "",
+                };
+                let old = diff.old_path.display();
+                writeln!(f, "diff{option} {old} {}", diff.path.display())?
+            }
+            _ => {}
+        }
+        let added = diff.status == FileStatus::Added;
+        let deleted = diff.status == FileStatus::Deleted;
+        if let Some(BinaryPatch::Stub) = diff.binary {
+            let old = match added {
+                true => DEV_NULL.to_string(),
+                false => diff.old_path.display().to_string(),
+            };
+            let new = match deleted {
+                true => DEV_NULL.to_string(),
+                false => diff.path.display().to_string(),
+            };
+            return writeln!(
+                f,
+                "{DIFF_SIGN_BINARY_STUB}{old} and {new} differ"
+            );
+        }
+        if diff.hunk.is_empty() || edits {
+            return Ok(());
+        }
+        let old = Self::label(&diff.old_path, &diff.old_timestamp, added);
+        let new = Self::label(&diff.path, &diff.new_timestamp, deleted);
+        let (old_sign, new_sign) = match format {
+            DiffFormat::Context => {
+                (DIFF_SIGN_CONTEXT_ORIGIN, DIFF_SIGN_HEADER_ORIGIN)
+            }
+            _ => (DIFF_SIGN_HEADER_ORIGIN, DIFF_SIGN_HEADER_NEW),
+        };
+        writeln!(f, "{old_sign} {old}")?;
+        writeln!(f, "{new_sign} {new}")
+    }
+}
+
+impl fmt::Display for BinaryHunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            BinaryKind::Literal => "literal",
+            BinaryKind::Delta => "delta",
+        };
+        writeln!(f, "{kind} {}", self.data.len())?;
+        for chunk in binary::deflate(&self.data).chunks(binary::LINE_BYTES) {
+            writeln!(
+                f,
+                "{}{}",
+                binary::line_len_char(chunk.len()),
+                binary::encode_base85(chunk)
+            )?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use crate::{parser::Parser, writer::*};
+
+    #[test]
+    fn test_write_git_udiff_roundtrip() {
+        for path in [
+            "test_data/simple.diffs",
+            "test_data/middle.diffs",
+            "test_data/composition/simple_app.diffs",
+            "test_data/composition/middle_rev.diffs",
+            "test_data/composition/files.diffs",
+            "test_data/composition/rename.diffs",
+            "test_data/binary/stub.diffs",
+            "test_data/no_newline.diffs",
+            "test_data/crlf.diffs",
+        ] {
+            let src = fs::read_to_string(path).unwrap();
+            let com = Parser::parse_git_udiff(&src).unwrap();
+            let written = Writer::write_git_udiff(&com);
+            print!("{}", written);
+            assert_eq!(written.as_str(), src.as_str());
+            assert_eq!(com.to_string().as_str(), src.as_str());
+        }
+    }
+
+    #[test]
+    fn test_write_unified_roundtrip() {
+        for path in [
+            "test_data/unified/tree.diffs",
+            "test_data/unified/single.diffs",
+        ] {
+            let src = fs::read_to_string(path).unwrap();
+            let com = Parser::parse_unified(&src).unwrap();
+            assert_eq!(Writer::write_unified(&com), src);
+            assert_eq!(com.to_string(), src);
+        }
+
+        // a git diff written as a plain unified diff
+        let src =
+            fs::read_to_string("test_data/composition/files.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let written = Writer::write_unified(&com);
+        assert!(
+            written.starts_with("--- /dev/null\n+++ added\n@@ -0,0 +1,3 @@")
+        );
+        let parsed = Parser::parse_unified(&written).unwrap();
+        assert_eq!(parsed.diff[0].status, FileStatus::Added);
+        assert_eq!(Writer::write_unified(&parsed), written);
+    }
+
+    #[test]
+    fn test_write_context_roundtrip() {
+        for path in [
+            "test_data/context/tree.diffs",
+            "test_data/context/single.diffs",
+        ] {
+            let src = fs::read_to_string(path).unwrap();
+            let com = Parser::parse_context(&src).unwrap();
+            assert_eq!(Writer::write_context(&com), src);
+            assert_eq!(com.to_string(), src);
+        }
+    }
+
+    #[test]
+    fn test_convert_unified_context() {
+        for (context, unified) in [
+            (
+                "test_data/context/tree.diffs",
+                "test_data/context/tree_unified.diffs",
+            ),
+            (
+                "test_data/context/single.diffs",
+                "test_data/context/single_unified.diffs",
+            ),
+        ] {
+            let context = fs::read_to_string(context).unwrap();
+            let unified = fs::read_to_string(unified).unwrap();
+            let from_context = Parser::parse_context(&context).unwrap();
+            let mut from_unified = Parser::parse_unified(&unified).unwrap();
+            // the commands and timestamps are the only other differences
+            for (diff, other) in
+                from_unified.diff.iter_mut().zip(&from_context.diff)
+            {
+                diff.command = other.command.clone();
+                diff.old_timestamp = other.old_timestamp.clone();
+                diff.new_timestamp = other.new_timestamp.clone();
+            }
+            assert_eq!(Writer::write_context(&from_unified), context);
+            assert_eq!(
+                Writer::write_unified(&from_context),
+                Writer::write_unified(&from_unified)
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_normal_ed_roundtrip() {
+        for path in [
+            "test_data/normal/tree.diffs",
+            "test_data/normal/single.diffs",
+        ] {
+            let src = fs::read_to_string(path).unwrap();
+            let com = Parser::parse_normal(&src).unwrap();
+            assert_eq!(Writer::write_normal(&com), src);
+            assert_eq!(com.to_string(), src);
+        }
+        for (path, target) in [
+            ("test_data/ed/single.diffs", "test_data/normal/main.rs.orig"),
+            ("test_data/ed/dot.diffs", "test_data/ed/dot.before"),
+        ] {
+            let src = fs::read_to_string(path).unwrap();
+            let com = Parser::parse_ed(&src, Path::new(target)).unwrap();
+            assert_eq!(Writer::write_ed(&com), src);
+        }
+        // the `Only in` lines are not kept
+        // the old tree of the script, its middle.rs is middle.before
+        let root = Path::new("test_data/ed_tree_write");
+        fs::create_dir_all(root.join("old/sub")).expect("failed to create dir");
+        fs::copy("test_data/middle.before", root.join("old/middle.rs"))
+            .expect("failed to copy");
+        fs::copy("test_data/ed/old/sub/list", root.join("old/sub/list"))
+            .expect("failed to copy");
+        let src = fs::read_to_string("test_data/ed/tree.diffs").unwrap();
+        let com = Parser::parse_ed(&src, root).unwrap();
+        fs::remove_dir_all(root).expect("failed to remove dir");
+        let kept: String = src
+            .split_inclusive('\n')
+            .filter(|line| !line.starts_with("Only in "))
+            .collect();
+        assert_eq!(Writer::write_ed(&com), kept);
+    }
+
+    #[test]
+    fn test_convert_normal() {
+        // the context is read from the old file
+        let src = fs::read_to_string("test_data/normal/single.diffs").unwrap();
+        let mut com = Parser::parse_normal(&src).unwrap();
+        let old = Path::new("test_data/normal/main.rs.orig");
+        com.add_context(old, 3).unwrap();
+        let unified =
+            fs::read_to_string("test_data/normal/single_unified.diffs")
+                .unwrap();
+        let from_unified = Parser::parse_unified(&unified).unwrap();
+        let diff = &mut com.diff[0];
+        diff.old_path = from_unified.diff[0].old_path.clone();
+        diff.path = from_unified.diff[0].path.clone();
+        diff.old_timestamp = from_unified.diff[0].old_timestamp.clone();
+        diff.new_timestamp = from_unified.diff[0].new_timestamp.clone();
+        assert_eq!(Writer::write_unified(&com), unified);
+
+        // and back, the paths are written as a `diff` line
+        let written = Writer::write_normal(&from_unified);
+        assert_eq!(written, format!("diff main.rs.orig main.rs\n{src}"));
+
+        let src =
+            fs::read_to_string("test_data/context/tree_unified.diffs").unwrap();
+        let com = Parser::parse_unified(&src.replace("-ruN ", "-rN ")).unwrap();
+        let normal = fs::read_to_string("test_data/normal/tree.diffs").unwrap();
+        assert_eq!(Writer::write_normal(&com), normal);
+        // `diff -e` leaves out the added and deleted files
+        let mut com =
+            Parser::parse_unified(&src.replace("-ruN ", "-re ")).unwrap();
+        com.diff.retain(|diff| diff.status == FileStatus::Modified);
+        let ed = fs::read_to_string("test_data/ed/tree.diffs").unwrap();
+        let ed: String = ed
+            .split_inclusive('\n')
+            .filter(|line| !line.starts_with("Only in "))
+            .collect();
+        assert_eq!(Writer::write_ed(&com), ed);
+    }
+
+    #[test]
+    fn test_write_mbox_roundtrip() {
+        let src = fs::read_to_string("test_data/mbox/series.mbox").unwrap();
+        let patches = Parser::parse_mbox(&src).unwrap();
+        let written = Writer::write_mbox(&patches);
+        // binary data is deflated again, the last patch is the blob
+        let blob = src.find("GIT binary patch").unwrap();
+        assert_eq!(written[..blob], src[..blob]);
+        let parsed = Parser::parse_mbox(&written).unwrap();
+        let (Some(binary), Some(expected)) = (
+            &parsed[4].diff.diff[0].binary,
+            &patches[4].diff.diff[0].binary,
+        ) else {
+            panic!("expect binary patches");
+        };
+        assert_eq!(binary_sizes(binary), binary_sizes(expected));
+        assert!(written.ends_with("new mode 100644\n-- \n2.39.5\n\n"));
+
+        let rename = |a: &str, b: &str| rename_name(Path::new(a), Path::new(b));
+        assert_eq!(
+            rename("src/a/lib.rs", "src/b/lib.rs"),
+            "src/{a => b}/lib.rs"
+        );
+        assert_eq!(rename("a.txt", "docs/a.txt"), "a.txt => docs/a.txt");
+        assert_eq!(rename("a/b.txt", "a/c/b.txt"), "a/{ => c}/b.txt");
+    }
+
+    #[test]
+    fn test_write_quoted_paths() {
+        let src = fs::read_to_string("test_data/quoted.diffs").unwrap();
+        let mut com = Parser::parse_git_udiff(&src).unwrap();
+        assert_eq!(com.to_git_udiff(), src);
+        // the commands are written from the paths as git does
+        for diff in &mut com.diff {
+            diff.command = None;
+        }
+        assert_eq!(com.to_git_udiff(), src);
+        let path = Path::new("dir/\u{7f}\r");
+        assert_eq!(quote_path("a/", path), r#""a/dir/\177\r""#);
+        assert_eq!(quote_path("b/", Path::new("my file")), "b/my file");
+    }
+
+    #[test]
+    fn test_write_bytes_roundtrip() {
+        let src = fs::read("test_data/latin1.diffs").unwrap();
+        let com = Parser::parse_git_udiff_bytes(&src).unwrap();
+        assert_eq!(Writer::write_git_udiff_bytes(&com), src);
+        // the string writer is lossy
+        assert!(com.to_string().contains("caf\u{fffd}"));
+        // only changed lines may be in another encoding
+        let body = src.iter().position(|b| *b == b'\n').unwrap();
+        let renamed = [&b"diff --git a/caf\xe9 b/caf\xe9"[..], &src[body..]];
+        let err = Parser::parse_git_udiff_bytes(&renamed.concat()).unwrap_err();
+        assert_eq!(err.reason(), "line is not valid UTF-8");
+    }
+
+    #[test]
+    fn test_write_generated() {
+        let old = fs::read_to_string("test_data/middle.before").unwrap();
+        let new = fs::read_to_string("test_data/middle.after").unwrap();
+        let com = DiffComposition {
+            format: DiffFormat::GitUdiff,
+            diff: vec![Diff::from_texts(&old, &new, "tests/vm.rs", 3)],
+            preamble: String::new(),
+            interstitial: Vec::new(),
+            trailer: String::new(),
+        };
+        let written = com.to_git_udiff();
+        print!("{}", written);
+
+        let parsed = Parser::parse_git_udiff(&written).unwrap();
+        let diff = parsed.diff.first().unwrap();
+        assert_eq!(diff.apply(&old).unwrap().as_str(), new.as_str());
+    }
+
+    #[test]
+    fn test_write_binary() {
+        let src = fs::read_to_string("test_data/binary/binary.diffs").unwrap();
+        let com = Parser::parse_git_udiff(&src).unwrap();
+        let written = com.to_git_udiff();
+        print!("{}", written);
+
+        // data is deflated again, so only the decoded hunks are compared
+        let parsed = Parser::parse_git_udiff(&written).unwrap();
+        for (diff, expected) in parsed.diff.iter().zip(&com.diff) {
+            let (
+                Some(BinaryPatch::Git { forward, reverse }),
+                Some(BinaryPatch::Git {
+                    forward: expected_forward,
+                    reverse: expected_reverse,
+                }),
+            ) = (&diff.binary, &expected.binary)
+            else {
+                panic!("expect binary patches");
+            };
+            assert_eq!(forward.kind, expected_forward.kind);
+            assert_eq!(forward.data, expected_forward.data);
+            let reverse = reverse.as_ref().unwrap();
+            let expected_reverse = expected_reverse.as_ref().unwrap();
+            assert_eq!(reverse.kind, expected_reverse.kind);
+            assert_eq!(reverse.data, expected_reverse.data);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+fn main() {
+    let a = 1;
+    println!("{a}");
+}
+
+fn helper() {
+    let x = 1;
+    let z = 3;
+    let w = 4;
+    let v = 5;
+    x + y + v
+}